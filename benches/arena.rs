@@ -0,0 +1,31 @@
+// Compares parsing into boxed trees (via `evaluate`) against parsing into a
+// reused `ExprArena`. Run with `cargo bench --bench arena`.
+use std::time::Instant;
+use expr::{evaluate, ExprArena};
+
+const ITERATIONS: u32 = 100_000;
+const EXPRESSIONS: [&str; 3] = [
+    "1 + 2 * 3",
+    "if(1 > 0, (1 + 3) * (2 + 1), -pi())",
+    "((1 + 2) * (3 + 4) - (5 + 6) * (7 + 8)) / ((9 - 1) * (2 + 2)) + approx(1, 1.5, 1)",
+];
+
+fn main() {
+    for expression in EXPRESSIONS.iter() {
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            std::hint::black_box(evaluate(std::hint::black_box(expression)).unwrap());
+        }
+        let boxed = start.elapsed();
+
+        let mut arena = ExprArena::new();
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let root = arena.parse(std::hint::black_box(expression)).unwrap();
+            std::hint::black_box(arena.eval(root, expression).unwrap());
+        }
+        let arena_time = start.elapsed();
+
+        println!("{:<90} boxed: {:>8.1?}  arena: {:>8.1?}", expression, boxed / ITERATIONS, arena_time / ITERATIONS);
+    }
+}
@@ -0,0 +1,29 @@
+// Compares evaluating constant expressions through a boxed tree (`evaluate`)
+// against folding them while parsing (`const_eval`). Run with `cargo bench --bench const_eval`.
+use std::time::Instant;
+use expr::{evaluate, const_eval};
+
+const ITERATIONS: u32 = 100_000;
+const EXPRESSIONS: [&str; 3] = [
+    "1 + 2 * 3",
+    "if(1 > 0, (1 + 3) * (2 + 1), -pi())",
+    "((1 + 2) * (3 + 4) - (5 + 6) * (7 + 8)) / ((9 - 1) * (2 + 2)) + approx(1, 1.5, 1)",
+];
+
+fn main() {
+    for expression in EXPRESSIONS.iter() {
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            std::hint::black_box(evaluate(std::hint::black_box(expression)).unwrap());
+        }
+        let boxed = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            std::hint::black_box(const_eval(std::hint::black_box(expression)).unwrap().unwrap());
+        }
+        let folded = start.elapsed();
+
+        println!("{:<90} boxed: {:>8.1?}  folded: {:>8.1?}", expression, boxed / ITERATIONS, folded / ITERATIONS);
+    }
+}
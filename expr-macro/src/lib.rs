@@ -0,0 +1,44 @@
+//! Compile-time evaluation of constant expressions.
+
+use proc_macro::TokenStream;
+use std::collections::HashMap;
+
+/// Parses and evaluates a constant expression during compilation and expands
+/// to the resulting `f32`. Malformed expressions and variables are compile errors.
+///
+/// ```
+/// use expr_macro::expr;
+/// assert_eq!(expr!(1 + 2 * 3), 7.0);
+/// assert_eq!(expr!(max(2, 5) >= 4 && 1), 1.0);
+/// ```
+///
+/// ```compile_fail
+/// use expr_macro::expr;
+/// let value = expr!(1 + * 3);
+/// ```
+///
+/// ```compile_fail
+/// use expr_macro::expr;
+/// let value = expr!(x + 1);
+/// ```
+#[proc_macro]
+pub fn expr(input: TokenStream) -> TokenStream {
+    let expression = input.to_string();
+    // no variables are bound, so anything but a constant expression fails
+    let vars: HashMap<String, f32> = HashMap::new();
+    let output = match expr::BoundContext::new(&vars).eval(&expression) {
+        Ok(value) => literal(value),
+        Err(err) => format!("compile_error!({:?})", format!("invalid expression `{}`: {}", expression, err))
+    };
+    output.parse().unwrap()
+}
+
+fn literal(value: f32) -> String {
+    if value.is_nan() {
+        "::core::f32::NAN".to_string()
+    } else if value.is_infinite() {
+        format!("{}::core::f32::INFINITY", if value < 0.0 {"-"} else {""})
+    } else {
+        format!("({:?}f32)", value)
+    }
+}
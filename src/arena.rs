@@ -0,0 +1,203 @@
+use super::{Error, ErrorKind, Position, Context, tokenizer, parser, short_circuit, eval_binary, eval_unary, eval_func, eval_range, range_variable};
+use std::ops::Range;
+
+/// Index of a node inside an `ExprArena`.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub struct NodeId(u32);
+
+#[derive(Debug)]
+pub enum Node {
+    Number(Position),
+    Variable(Position),
+    Func { name: Position, params: Range<u32> }, // range into the arena's params list
+    Unary { expr: NodeId, operator_ix: u8, at: u32 },
+    Binary { left: NodeId, right: NodeId, operator_ix: u8, at: u32 },
+    Index { base: Position, index: NodeId },
+    Ternary { condition: NodeId, at: u32, then: NodeId, otherwise: NodeId }
+}
+
+/// Expression tree stored as a flat list of nodes linked by indices
+/// instead of boxes. Parsing into the same arena again reuses its buffers,
+/// so repeated parsing does not allocate once the buffers have grown.
+#[derive(Debug, Default)]
+pub struct ExprArena {
+    nodes: Vec<Node>,
+    params: Vec<NodeId>,
+}
+
+impl ExprArena {
+
+    pub fn new() -> ExprArena {
+        ExprArena::default()
+    }
+
+    /// Parses `expression` into the arena, discarding any previous tree,
+    /// and returns the root node.
+    pub fn parse(&mut self, expression: &str) -> Result<NodeId, Error> {
+        self.nodes.clear();
+        self.params.clear();
+        let mut tokens = tokenizer::Tokens::new(expression);
+        parser::parse_with(&mut tokens, self)
+    }
+
+    /// The node `id` refers to, `None` for an id from another or an earlier parse.
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0 as usize)
+    }
+
+    pub fn params(&self, range: &Range<u32>) -> Option<&[NodeId]> {
+        self.params.get(range.start as usize .. range.end as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Evaluates the tree rooted at `id`; `expression` must be the string it was parsed from.
+    pub fn eval(&self, id: NodeId, expression: &str) -> Result<f32, Error> {
+        self.eval_node(id, &Context::new(expression, &|_| None))
+    }
+
+    fn eval_node(&self, id: NodeId, ctx: &Context) -> Result<f32, Error> {
+        match self.node(id).ok_or_else(|| missing(id))? {
+            Node::Number(pos) => ctx.number(*pos),
+            Node::Binary { left, right, operator_ix, at } => {
+                let left = self.eval_node(*left, ctx)?;
+                if let Some(value) = short_circuit(*operator_ix, *at, left, &ctx.options)? {
+                    return Ok(value);
+                }
+                let right = self.eval_node(*right, ctx)?;
+                eval_binary(*operator_ix, *at, left, right, &ctx.options)
+            }
+            Node::Unary { expr, operator_ix, at } => {
+                eval_unary(*operator_ix, *at, self.eval_node(*expr, ctx)?)
+            }
+            Node::Variable(pos) => ctx.variable(*pos),
+            Node::Ternary { condition, then, otherwise, .. } => {
+                let taken = if self.eval_node(*condition, ctx)? > 0.0 { then } else { otherwise };
+                self.eval_node(*taken, ctx)
+            }
+            Node::Index { base, index } => ctx.element(*base, self.eval_node(*index, ctx)?, self.at(*index)),
+            Node::Func { name, params } => {
+                let name_str = &ctx.source[name.to_range()];
+                let params = self.params(params).ok_or_else(|| missing(id))?;
+                if let Some(Node::Variable(var)) = range_variable(name_str, params).and_then(|param| self.node(*param)) {
+                    let var = &ctx.source[var.to_range()];
+                    if (ctx.resolve)(var).is_none() {
+                        return eval_range(name_str, name.at, var, &params[1..], ctx, &|param, ctx| self.eval_node(*param, ctx));
+                    }
+                }
+                eval_func(name_str, name.at, &ctx.options, params, &mut |param| self.eval_node(*param, ctx))
+            }
+        }
+    }
+
+    fn at(&self, id: NodeId) -> u32 {
+        match self.node(id) {
+            Some(Node::Number(pos) | Node::Variable(pos) | Node::Index { base: pos, .. }) => pos.at,
+            Some(Node::Func { name, .. }) => name.at,
+            Some(Node::Unary { at, .. } | Node::Binary { at, .. } | Node::Ternary { at, .. }) => *at,
+            None => 0
+        }
+    }
+
+    fn push(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+}
+
+// an id kept from another arena or from before the arena was parsed into again
+fn missing(id: NodeId) -> Error {
+    Error {
+        error: format!("Node {} is not in this arena", id.0),
+        at: 0,
+        kind: ErrorKind::Other
+    }
+}
+
+impl parser::Builder for ExprArena {
+    type Node = NodeId;
+
+    fn number(&mut self, pos: Position) -> NodeId {
+        self.push(Node::Number(pos))
+    }
+
+    fn variable(&mut self, pos: Position) -> NodeId {
+        self.push(Node::Variable(pos))
+    }
+
+    fn func(&mut self, name: Position, params: Vec<NodeId>) -> NodeId {
+        let start = self.params.len() as u32;
+        self.params.extend(params);
+        let end = self.params.len() as u32;
+        self.push(Node::Func { name, params: start .. end })
+    }
+
+    fn ternary(&mut self, condition: NodeId, at: u32, then: NodeId, otherwise: NodeId) -> NodeId {
+        self.push(Node::Ternary { condition, at, then, otherwise })
+    }
+
+    fn index(&mut self, base: Position, index: NodeId) -> NodeId {
+        self.push(Node::Index { base, index })
+    }
+
+    fn unary(&mut self, at: u32, operator_ix: u8, expr: NodeId) -> NodeId {
+        self.push(Node::Unary { expr, operator_ix, at })
+    }
+
+    fn binary(&mut self, left: NodeId, at: u32, operator_ix: u8, right: NodeId) -> NodeId {
+        self.push(Node::Binary { left, right, operator_ix, at })
+    }
+}
+
+
+#[cfg(test)]
+mod arena_should {
+    use super::*;
+    use crate::evaluate;
+
+    fn eval(expression: &str) -> f32 {
+        let mut arena = ExprArena::new();
+        let root = arena.parse(expression).unwrap();
+        arena.eval(root, expression).unwrap()
+    }
+
+    #[test]
+    fn match_boxed_evaluation() {
+        for expression in &["1", "--2", "3 * (2 + 1)", "12/2/3", "pi()", "if(1 <= 0, 10, -1)", "sum(i, 1, 4, i * 2)"] {
+            assert_eq!(eval(expression), evaluate(expression).unwrap());
+        }
+    }
+
+    #[test]
+    fn reuse_nodes_between_parses() {
+        let mut arena = ExprArena::new();
+        arena.parse("1 + 2 * 3").unwrap();
+        assert_eq!(arena.len(), 5);
+        let root = arena.parse("max(1, 2)").unwrap();
+        assert_eq!(arena.len(), 3);
+        assert_matches!(arena.node(root), Some(Node::Func { .. }));
+    }
+
+    #[test]
+    fn error_on_stale_node_id() {
+        let mut arena = ExprArena::new();
+        let root = arena.parse("1 + 2 * 3").unwrap();
+        arena.parse("1").unwrap();
+        assert_matches!(arena.node(root), None);
+        assert_eq!(arena.eval(root, "1").unwrap_err().error, "Node 4 is not in this arena");
+        assert_matches!(ExprArena::new().eval(root, "1"), Err(..));
+    }
+
+    #[test]
+    fn report_parse_errors() {
+        let mut arena = ExprArena::new();
+        assert_matches!(arena.parse("(1 + 2"), Err(..));
+    }
+}
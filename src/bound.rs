@@ -0,0 +1,60 @@
+use super::{Error, Context, tokenizer, parser, eval_expr};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Variables bound once and shared by many evaluations, e.g. in a hot loop
+/// evaluating different formulas against the same inputs. Variables shadow
+/// constants of the same name.
+pub struct BoundContext<'a, K = String> {
+    vars: &'a HashMap<K, f32>,
+    constants: Option<&'a HashMap<K, f32>>
+}
+
+impl<'a, K> BoundContext<'a, K> where K: Borrow<str> + Hash + Eq {
+
+    pub fn new(vars: &'a HashMap<K, f32>) -> BoundContext<'a, K> {
+        BoundContext { vars, constants: None }
+    }
+
+    pub fn with_constants(self, constants: &'a HashMap<K, f32>) -> BoundContext<'a, K> {
+        BoundContext { constants: Some(constants), ..self }
+    }
+
+    pub fn eval(&self, expression: &str) -> Result<f32, Error> {
+        let mut tokens = tokenizer::Tokens::new(expression);
+        let expr = parser::parse(&mut tokens)?;
+        let resolve = |name: &str| self.vars.get(name)
+            .or_else(|| self.constants.and_then(|constants| constants.get(name)))
+            .copied();
+        eval_expr(&expr, &Context::new(expression, &resolve))
+    }
+}
+
+
+#[cfg(test)]
+mod bound_should {
+    use super::*;
+
+    #[test]
+    fn reuse_variables_across_expressions() {
+        let mut vars = HashMap::new();
+        vars.insert("width".to_string(), 4.0);
+        vars.insert("height".to_string(), 2.5);
+        let bound = BoundContext::new(&vars);
+        assert_eq!(bound.eval("width * height").unwrap(), 10.0);
+        assert_eq!(bound.eval("2 * (width + height)").unwrap(), 13.0);
+        assert_matches!(bound.eval("depth"), Err(..));
+    }
+
+    #[test]
+    fn fall_back_to_constants() {
+        let mut vars = HashMap::new();
+        vars.insert("g", 10.0);
+        let mut constants = HashMap::new();
+        constants.insert("g", 9.81);
+        constants.insert("c", 3.0);
+        let bound = BoundContext::new(&vars).with_constants(&constants);
+        assert_eq!(bound.eval("g * c").unwrap(), 30.0);
+    }
+}
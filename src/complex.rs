@@ -0,0 +1,143 @@
+use super::{Error, tokenizer, parser, numeric};
+use numeric::Numeric;
+use std::cmp::Ordering;
+
+/// A complex number `re + im*i`.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Complex {
+        Complex { re, im }
+    }
+
+    fn real(re: f32) -> Complex {
+        Complex { re, im: 0.0 }
+    }
+
+    fn abs(self) -> f32 {
+        self.re.hypot(self.im)
+    }
+
+    // the principal root, with a non-negative real part
+    fn sqrt(self) -> Complex {
+        let modulus = self.abs();
+        let re = ((modulus + self.re) / 2.0).sqrt();
+        let im = ((modulus - self.re) / 2.0).sqrt();
+        Complex { re, im: if self.im < 0.0 { -im } else { im } }
+    }
+}
+
+impl Numeric for Complex {
+    // a trailing `i` makes the literal imaginary, as joined by the tokenizer
+    fn literal(text: &str) -> Result<Complex, String> {
+        let (number, imaginary) = match text.strip_suffix('i') {
+            Some(number) => (number, true),
+            None => (text, false)
+        };
+        let value = number.parse::<f32>().map_err(|_| format!("Invalid number '{}'", text))?;
+        Ok(if imaginary { Complex::new(0.0, value) } else { Complex::real(value) })
+    }
+
+    fn variable(name: &str) -> Option<Complex> {
+        match name {
+            "i" => Some(Complex::new(0.0, 1.0)),
+            _ => None
+        }
+    }
+
+    fn from_bool(value: bool) -> Complex {
+        Complex::real(if value {1.0} else {0.0})
+    }
+
+    fn truthy(&self) -> Result<bool, String> {
+        if self.im != 0.0 {
+            return Err(format!("Complex number {} + {}i is neither true nor false", self.re, self.im));
+        }
+        Ok(self.re > 0.0)
+    }
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+
+    fn add(self, rhs: Complex) -> Result<Complex, String> {
+        Ok(Complex::new(self.re + rhs.re, self.im + rhs.im))
+    }
+
+    fn sub(self, rhs: Complex) -> Result<Complex, String> {
+        Ok(Complex::new(self.re - rhs.re, self.im - rhs.im))
+    }
+
+    fn mul(self, rhs: Complex) -> Result<Complex, String> {
+        Ok(Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re))
+    }
+
+    fn div(self, rhs: Complex) -> Result<Complex, String> {
+        let denom = rhs.re * rhs.re + rhs.im * rhs.im;
+        Ok(Complex::new((self.re * rhs.re + self.im * rhs.im) / denom, (self.im * rhs.re - self.re * rhs.im) / denom))
+    }
+
+    // complex numbers have no order, only equality
+    fn compare(&self, _rhs: &Complex) -> Result<Option<Ordering>, String> {
+        Err("Complex numbers can only be compared with '='".to_string())
+    }
+
+    fn equals(&self, rhs: &Complex) -> Result<bool, String> {
+        Ok(self == rhs)
+    }
+
+    fn call(name: &str, args: &[Complex]) -> Option<Result<Complex, String>> {
+        match (name, args) {
+            ("pi", []) => Some(Ok(Complex::real(std::f32::consts::PI))),
+            ("e", []) => Some(Ok(Complex::real(std::f32::consts::E))),
+            ("tau", []) => Some(Ok(Complex::real(std::f32::consts::TAU))),
+            ("abs", &[z]) => Some(Ok(Complex::real(z.abs()))),
+            ("sqrt", &[z]) => Some(Ok(z.sqrt())),
+            _ => None
+        }
+    }
+}
+
+/// Evaluates the expression over complex numbers, where `i` is the imaginary
+/// unit and a number directly followed by `i`, like `4i`, is imaginary.
+pub fn evaluate_complex(expression: &str) -> Result<Complex, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression).with_imaginary_literals();
+    let expr = parser::parse(&mut tokens)?;
+    numeric::eval(&expr, expression)
+}
+
+
+#[cfg(test)]
+mod complex_should {
+    use super::*;
+
+    #[test]
+    fn square_imaginary_unit() {
+        assert_eq!(evaluate_complex("i * i").unwrap(), Complex::new(-1.0, 0.0));
+        assert_eq!(evaluate_complex("i * i = -1").unwrap(), Complex::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn compute_modulus_and_root() {
+        assert_eq!(evaluate_complex("abs(3 + 4i)").unwrap(), Complex::new(5.0, 0.0));
+        assert_eq!(evaluate_complex("sqrt(-4)").unwrap(), Complex::new(0.0, 2.0));
+        assert_eq!(evaluate_complex("sqrt(2i)").unwrap(), Complex::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn divide() {
+        assert_eq!(evaluate_complex("(1 + 2i) / (3 - 4i)").unwrap(), Complex::new(-0.2, 0.4));
+    }
+
+    #[test]
+    fn error_on_ordering() {
+        assert_matches!(evaluate_complex("1 + i < 2"), Err(..));
+        assert_matches!(evaluate_complex("if(i, 1, 2)"), Err(..));
+        assert_matches!(evaluate_complex("x"), Err(..));
+    }
+}
@@ -0,0 +1,75 @@
+use super::{Error, Position, operator, tokenizer, parser};
+
+// builds nothing but the score of each subtree
+struct Scorer;
+
+impl parser::Builder for Scorer {
+    type Node = u32;
+
+    fn number(&mut self, _pos: Position) -> u32 {
+        1
+    }
+
+    fn variable(&mut self, _pos: Position) -> u32 {
+        1
+    }
+
+    fn func(&mut self, _name: Position, params: Vec<u32>) -> u32 {
+        3 + params.len() as u32 + params.iter().sum::<u32>()
+    }
+
+    // the same as `if(a, b, c)`
+    fn ternary(&mut self, condition: u32, _at: u32, then: u32, otherwise: u32) -> u32 {
+        6 + condition + then + otherwise
+    }
+
+    fn index(&mut self, _base: Position, index: u32) -> u32 {
+        1 + index
+    }
+
+    fn unary(&mut self, _at: u32, _operator_ix: u8, expr: u32) -> u32 {
+        1 + expr
+    }
+
+    fn binary(&mut self, left: u32, _at: u32, operator_ix: u8, right: u32) -> u32 {
+        let weight = if operator::from(operator_ix).char1 == '^' {5} else {2};
+        weight + left + right
+    }
+}
+
+/// Parses the expression and scores how expensive it is to evaluate, so callers
+/// can reject formulas above a threshold. Numbers and variables count 1, prefix
+/// operators and element accesses like `v[i]` 1, binary operators 2 except `^` which counts 5, and function calls
+/// 3 plus 1 per parameter, each on top of the scores of their operands.
+/// `a ? b : c` counts the same as `if(a, b, c)`.
+/// Parentheses are free.
+pub fn complexity_score(expression: &str) -> Result<u32, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    parser::parse_with(&mut tokens, &mut Scorer)
+}
+
+
+#[cfg(test)]
+mod complexity_should {
+    use super::*;
+
+    #[test]
+    fn weigh_nodes() {
+        assert_eq!(complexity_score("x").unwrap(), 1);
+        assert_eq!(complexity_score("-(1 + x)").unwrap(), 5);
+        assert_eq!(complexity_score("dist(a, 2)").unwrap(), 7);
+        assert_eq!(complexity_score("x ^ 2").unwrap(), 7);
+    }
+
+    #[test]
+    fn score_nested_functions_above_flat_arithmetic() {
+        let simple = complexity_score("a + b * 2").unwrap();
+        let nested = complexity_score("clamp(dist(a, b), 0, sin_norm(b))").unwrap();
+        assert!(nested > 2 * simple);
+    }
+
+    #[test]
+    fn error_on_invalid_expression() {
+        assert_matches!(complexity_score("1 +"), Err(..));
+    }
+}
@@ -0,0 +1,94 @@
+use super::{Error, Position, Context, IdentKind, tokenizer, parser, classify_identifier,
+            short_circuit, eval_binary, eval_unary, eval_func};
+
+// evaluates each node as soon as it is parsed; None once a variable or an
+// unknown function shows up, since only the caller knows what they mean
+struct Folder<'c, 'a> {
+    ctx: &'c Context<'a>
+}
+
+type Folded = Option<Result<f32, Error>>;
+
+impl parser::Builder for Folder<'_, '_> {
+    type Node = Folded;
+
+    fn number(&mut self, pos: Position) -> Folded {
+        Some(self.ctx.number(pos))
+    }
+
+    fn variable(&mut self, _pos: Position) -> Folded {
+        None
+    }
+
+    fn func(&mut self, name: Position, params: Vec<Folded>) -> Folded {
+        let name_str = &self.ctx.source[name.to_range()];
+        if !matches!(classify_identifier(name_str), IdentKind::BuiltinFunction | IdentKind::Constant) && name_str != "if" {
+            return None;
+        }
+        let params = params.into_iter().collect::<Option<Vec<_>>>()?;
+        Some(eval_func(name_str, name.at, &self.ctx.options, &params, &mut |param| param.clone()))
+    }
+
+    fn index(&mut self, _base: Position, _index: Folded) -> Folded {
+        None
+    }
+
+    fn unary(&mut self, at: u32, operator_ix: u8, expr: Folded) -> Folded {
+        Some(expr?.and_then(|value| eval_unary(operator_ix, at, value)))
+    }
+
+    // an error on the side `&&` or `||` skips does not count, as in `evaluate`
+    fn binary(&mut self, left: Folded, at: u32, operator_ix: u8, right: Folded) -> Folded {
+        let (left, right) = (left?, right?);
+        Some(left.and_then(|left| match short_circuit(operator_ix, at, left, &self.ctx.options)? {
+            Some(value) => Ok(value),
+            None => eval_binary(operator_ix, at, left, right?, &self.ctx.options)
+        }))
+    }
+
+    fn ternary(&mut self, condition: Folded, _at: u32, then: Folded, otherwise: Folded) -> Folded {
+        let (condition, then, otherwise) = (condition?, then?, otherwise?);
+        Some(condition.and_then(|condition| if condition > 0.0 { then } else { otherwise }))
+    }
+}
+
+/// Evaluates an expression of numbers and built-in functions while parsing it,
+/// without building a tree. `None` when it has variables or calls functions
+/// that are not built in, so the caller can fall back to `evaluate_with` or similar.
+pub fn const_eval(expression: &str) -> Option<Result<f32, Error>> {
+    let ctx = Context::new(expression, &|_| None);
+    let mut tokens = tokenizer::Tokens::new(expression);
+    match parser::parse_with(&mut tokens, &mut Folder { ctx: &ctx }) {
+        Ok(folded) => folded,
+        Err(error) => Some(Err(error))
+    }
+}
+
+
+#[cfg(test)]
+mod const_eval_should {
+    use super::*;
+    use crate::evaluate;
+
+    #[test]
+    fn agree_with_evaluate() {
+        for expression in &["1 + 2 * 3", "if(1 > 0, (1 + 3) * (2 + 1), -pi())", "2 ^ 3 ^ 2 % 7", "0 && max()",
+                            "1 ? 2 : max()", "!(1 < 2) || sqrt(16) = 4", "1_000 / 0", "max()", "1 +", "(1"] {
+            match (const_eval(expression).unwrap(), evaluate(expression)) {
+                (Ok(folded), Ok(value)) => assert_eq!(folded, value, "{}", expression),
+                (Err(folded), Err(error)) => assert_eq!((folded.error, folded.at), (error.error, error.at), "{}", expression),
+                (folded, value) => panic!("{}: {:?} but {:?}", expression, folded, value)
+            }
+        }
+    }
+
+    #[test]
+    fn give_up_on_variables_and_unknown_functions() {
+        assert!(const_eval("1 + x").is_none());
+        assert!(const_eval("v[0]").is_none());
+        assert!(const_eval("0 && x").is_none());
+        assert!(const_eval("sum(i, 1, 3, i)").is_none());
+        assert!(const_eval("custom(1)").is_none());
+        assert_eq!(const_eval("sum(1, 2, 3)").unwrap().unwrap(), 6.0);
+    }
+}
@@ -0,0 +1,99 @@
+use super::{Error, EvalOptions, Context, tokenizer, parser, operator, eval_expr};
+use crate::real::Real;
+use std::cell::RefCell;
+
+/// A place where evaluation produced a value that is probably not what the
+/// author meant, even though it is not an error.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub at: u32, // the operator, function name or variable
+}
+
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub enum DiagnosticKind {
+    /// `/` or `%` with a zero divisor.
+    DivisionByZero,
+    /// A finite computation that grew past the largest f32.
+    Overflow,
+    /// An operation or function that turned non-NaN input into NaN, like `sqrt(-1)`.
+    Nan,
+    /// A variable without a value that evaluated to the `UnknownVariables::DefaultTo` value,
+    /// which often hides a typo in its name.
+    DefaultedVariable,
+}
+
+/// Evaluates the expression and reports every operation where the result first
+/// became non-finite, so a UI can highlight where a formula went wrong.
+pub fn evaluate_with_diagnostics(expression: &str) -> Result<(f32, Vec<Diagnostic>), Error> {
+    evaluate_with_diagnostics_and_options(expression, &EvalOptions::default())
+}
+
+/// Like `evaluate_with_diagnostics`, and also reports each variable that fell
+/// back to its default under `UnknownVariables::DefaultTo`.
+pub fn evaluate_with_diagnostics_and_options(expression: &str, options: &EvalOptions) -> Result<(f32, Vec<Diagnostic>), Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let diagnostics = RefCell::new(vec![]);
+    let ctx = Context::new(expression, &|_| None).with_options(options.clone()).with_diagnostics(&diagnostics);
+    let value = eval_expr(&expr, &ctx)?;
+    Ok((value, diagnostics.into_inner()))
+}
+
+pub(crate) fn binary_hazard<F: Real>(operator_ix: u8, left: F, right: F, value: F) -> Option<DiagnosticKind> {
+    let divides = matches!(operator::from(operator_ix).char1, '/' | '%');
+    if divides && right == F::ZERO && !left.is_nan() {
+        Some(DiagnosticKind::DivisionByZero)
+    } else if value.is_infinite() && left.is_finite() && right.is_finite() {
+        Some(DiagnosticKind::Overflow)
+    } else if value.is_nan() && !left.is_nan() && !right.is_nan() {
+        Some(DiagnosticKind::Nan)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn result_hazard<F: Real>(value: F) -> Option<DiagnosticKind> {
+    if value.is_nan() {
+        Some(DiagnosticKind::Nan)
+    } else if value.is_infinite() {
+        Some(DiagnosticKind::Overflow)
+    } else {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod diagnostics_should {
+    use super::*;
+
+    fn diagnostics(expression: &str) -> Vec<(DiagnosticKind, u32)> {
+        let (_, diagnostics) = evaluate_with_diagnostics(expression).unwrap();
+        diagnostics.iter().map(|diagnostic| (diagnostic.kind, diagnostic.at)).collect()
+    }
+
+    #[test]
+    fn locate_division_by_zero_and_nan() {
+        assert_eq!(diagnostics("1/0 + sqrt(-1)"), vec![(DiagnosticKind::DivisionByZero, 1), (DiagnosticKind::Nan, 6)]);
+    }
+
+    #[test]
+    fn report_only_where_values_become_non_finite() {
+        assert_eq!(diagnostics("2 ^ 127 * 4"), vec![(DiagnosticKind::Overflow, 8)]);
+        assert_eq!(diagnostics("floor(1 / 0) * 2"), vec![(DiagnosticKind::DivisionByZero, 8)]);
+        assert_eq!(diagnostics("(1/0) - (1/0)"), vec![(DiagnosticKind::DivisionByZero, 2), (DiagnosticKind::DivisionByZero, 10), (DiagnosticKind::Nan, 6)]);
+        assert_eq!(diagnostics("1 + 2"), vec![]);
+    }
+
+    #[test]
+    fn report_defaulted_variables() {
+        let options = EvalOptions { unknown_variables: crate::UnknownVariables::DefaultTo(1.0), ..EvalOptions::default() };
+        let (value, diagnostics) = evaluate_with_diagnostics_and_options("x + 1", &options).unwrap();
+        assert_eq!(value, 2.0);
+        assert_eq!(diagnostics, vec![Diagnostic { kind: DiagnosticKind::DefaultedVariable, at: 0 }]);
+        assert!(evaluate_with_diagnostics("x + 1").is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use super::{Error, Position, tokenizer, parser};
+use crate::spans::{Kind, Tree, TreeBuilder};
+
+/// A structural difference between two expressions, with positions into the
+/// expression each side was parsed from.
+#[derive(Debug, PartialEq)]
+pub enum TreeDiff {
+    /// The subtree at `before` was replaced by the one at `after`.
+    Changed { before: Position, after: Position },
+    /// A function parameter only the second expression has.
+    Added(Position),
+    /// A function parameter only the first expression has.
+    Removed(Position),
+}
+
+/// Compares the trees of two expressions and lists the outermost subtrees that
+/// differ, in source order. Parentheses and whitespace do not count as changes.
+pub fn diff(a: &str, b: &str) -> Result<Vec<TreeDiff>, Error> {
+    let before = parser::parse_with(&mut tokenizer::Tokens::new(a), &mut TreeBuilder::new(a))?;
+    let after = parser::parse_with(&mut tokenizer::Tokens::new(b), &mut TreeBuilder::new(b))?;
+    let mut diffs = vec![];
+    compare(&before, &after, &mut diffs);
+    Ok(diffs)
+}
+
+fn compare(before: &Tree, after: &Tree, diffs: &mut Vec<TreeDiff>) {
+    let same_arity = before.children.len() == after.children.len();
+    // only function calls may differ in their number of children
+    if before.kind != after.kind || (!same_arity && !matches!(before.kind, Kind::Func(_))) {
+        diffs.push(TreeDiff::Changed { before: before.span, after: after.span });
+        return;
+    }
+    for (before, after) in before.children.iter().zip(after.children.iter()) {
+        compare(before, after, diffs);
+    }
+    let common = before.children.len().min(after.children.len());
+    diffs.extend(before.children[common..].iter().map(|param| TreeDiff::Removed(param.span)));
+    diffs.extend(after.children[common..].iter().map(|param| TreeDiff::Added(param.span)));
+}
+
+
+#[cfg(test)]
+mod diff_should {
+    use super::*;
+
+    #[test]
+    fn report_changed_literal() {
+        let diffs = diff("1 + 2", "1 + 3").unwrap();
+        assert_eq!(diffs, vec![TreeDiff::Changed { before: Position { at: 4, len: 1 }, after: Position { at: 4, len: 1 } }]);
+    }
+
+    #[test]
+    fn ignore_layout() {
+        assert!(diff("(a + dist(1, b)) * 2", "(a+dist( 1,b ))*2").unwrap().is_empty());
+    }
+
+    #[test]
+    fn report_whole_subtree_on_changed_operator() {
+        let diffs = diff("x * (1 + 2)", "x * (1 - 2)").unwrap();
+        assert_eq!(diffs, vec![TreeDiff::Changed { before: Position { at: 4, len: 7 }, after: Position { at: 4, len: 7 } }]);
+    }
+
+    #[test]
+    fn report_added_and_removed_params() {
+        let diffs = diff("max(a, b)", "max(a, c, 2 * d)").unwrap();
+        assert_eq!(diffs, vec![
+            TreeDiff::Changed { before: Position { at: 7, len: 1 }, after: Position { at: 7, len: 1 } },
+            TreeDiff::Added(Position { at: 10, len: 5 }),
+        ]);
+        assert_eq!(diff("min(a, b)", "min(a)").unwrap(), vec![TreeDiff::Removed(Position { at: 7, len: 1 })]);
+        assert_eq!(diff("min(a)", "max(a)").unwrap(), vec![TreeDiff::Changed { before: Position { at: 0, len: 6 }, after: Position { at: 0, len: 6 } }]);
+    }
+
+    #[test]
+    fn error_on_invalid_expression() {
+        assert_matches!(diff("1 +", "1"), Err(..));
+        assert_matches!(diff("1", "(1"), Err(..));
+    }
+}
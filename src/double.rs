@@ -0,0 +1,39 @@
+use super::{Error, Context, tokenizer, parser, eval_expr};
+
+/// Evaluates the expression in f64, for results that need more precision
+/// than `evaluate` gives.
+pub fn evaluate_f64(expression: &str) -> Result<f64, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    eval_expr(&expr, &Context::new(expression, &|_| None))
+}
+
+
+#[cfg(test)]
+mod double_should {
+    use super::*;
+    use std::f64::consts;
+
+    #[test]
+    fn use_full_precision_constants() {
+        assert_eq!(evaluate_f64("pi()").unwrap(), consts::PI);
+        assert_eq!(evaluate_f64("e()").unwrap(), consts::E);
+        assert_eq!(evaluate_f64("tau() / 2").unwrap(), consts::PI);
+    }
+
+    #[test]
+    fn keep_more_precision_than_f32() {
+        assert_eq!(evaluate_f64("16777216 + 1").unwrap(), 16777217.0);
+        assert_eq!(evaluate_f64("dist(1, 3) * 2 > 3").unwrap(), 1.0);
+        assert_eq!(evaluate_f64("16777219 % 2").unwrap(), 1.0);
+        assert_eq!(evaluate_f64("2 ^ 30 + 1").unwrap(), 1073741825.0);
+        assert_eq!(evaluate_f64("0.1 + 0.2").unwrap(), 0.1 + 0.2);
+    }
+
+    #[test]
+    fn share_built_in_functions_with_f32() {
+        assert_eq!(evaluate_f64("sum(i, 1, 100, i) + floor(2.5) + max(1, 3)").unwrap(), 5055.0);
+        assert_eq!(evaluate_f64("sqrt(2)").unwrap(), consts::SQRT_2);
+        assert_eq!(evaluate_f64("x + 1").unwrap_err().error, "Unknown variable 'x'");
+    }
+}
@@ -0,0 +1,113 @@
+use super::{Error, tokenizer, parser, operator};
+use crate::spans::{Kind, Tree, TreeBuilder};
+
+/// Describes the expression in plain English, e.g. "multiply the sum of 1 and 2
+/// by 3" for `(1 + 2) * 3`.
+pub fn explain(expression: &str) -> Result<String, Error> {
+    let tree = parser::parse_with(&mut tokenizer::Tokens::new(expression), &mut TreeBuilder::new(expression))?;
+    Ok(instruction(&tree))
+}
+
+// the outermost operation as something to do
+fn instruction(tree: &Tree) -> String {
+    match (&tree.kind, &tree.children[..]) {
+        (Kind::Binary(operator_ix), [left, right]) => {
+            let (left, right) = (phrase(left), phrase(right));
+            match operator::from(*operator_ix).to_string().as_str() {
+                "+" => format!("add {} and {}", left, right),
+                "-" => format!("subtract {} from {}", right, left),
+                "*" => format!("multiply {} by {}", left, right),
+                "/" => format!("divide {} by {}", left, right),
+                "%" => format!("take the remainder of {} divided by {}", left, right),
+                "^" => format!("raise {} to the power of {}", left, right),
+                _ => format!("check {}", phrase(tree))
+            }
+        }
+        (Kind::Unary(operator_ix), [expr]) if operator::from(*operator_ix).char1 == '-' => format!("negate {}", phrase(expr)),
+        (Kind::Unary(operator_ix), _) if operator::from(*operator_ix).char1 == '!' => format!("check {}", phrase(tree)),
+        (Kind::Func(name), params) if !params.is_empty() && *name != "if" => format!("call {} with {}", name, list(params)),
+        _ => format!("take {}", phrase(tree))
+    }
+}
+
+// the value of a subtree as a noun phrase
+fn phrase(tree: &Tree) -> String {
+    match (&tree.kind, &tree.children[..]) {
+        (Kind::Number(text), _) | (Kind::Variable(text), _) => text.to_string(),
+        (Kind::Index(base), [index]) => format!("element {} of {}", phrase(index), base),
+        (Kind::Unary(operator_ix), [expr]) if operator::from(*operator_ix).char1 == '-' => format!("the negation of {}", phrase(expr)),
+        (Kind::Unary(operator_ix), [expr]) if operator::from(*operator_ix).char1 == '!' => format!("whether it is not true that {}", condition(expr)),
+        (Kind::Unary(_), [expr]) => phrase(expr),
+        (Kind::Binary(operator_ix), [left, right]) => {
+            let operator = operator::from(*operator_ix).to_string();
+            match operator.as_str() {
+                "&&" => return format!("whether {} and {}", condition(left), condition(right)),
+                "||" => return format!("whether {} or {}", condition(left), condition(right)),
+                "xor" => return format!("whether either {} or {} but not both", condition(left), condition(right)),
+                _ => {}
+            }
+            let (left, right) = (phrase(left), phrase(right));
+            match operator.as_str() {
+                "+" => format!("the sum of {} and {}", left, right),
+                "-" => format!("the difference of {} and {}", left, right),
+                "*" => format!("the product of {} and {}", left, right),
+                "/" => format!("the quotient of {} and {}", left, right),
+                "%" => format!("the remainder of {} divided by {}", left, right),
+                "^" => format!("{} to the power of {}", left, right),
+                "<" => format!("whether {} is less than {}", left, right),
+                ">" => format!("whether {} is greater than {}", left, right),
+                "<=" => format!("whether {} is at most {}", left, right),
+                ">=" => format!("whether {} is at least {}", left, right),
+                "=" | "==" => format!("whether {} equals {}", left, right),
+                "!=" => format!("whether {} differs from {}", left, right),
+                other => format!("{} {} {}", left, other, right)
+            }
+        }
+        (Kind::Func("if"), [condition, then, otherwise]) | (Kind::Ternary, [condition, then, otherwise]) =>
+            format!("{} if {}, otherwise {}", phrase(then), self::condition(condition), phrase(otherwise)),
+        (Kind::Func(name), []) => name.to_string(),
+        (Kind::Func(name), params) => format!("{} of {}", name, list(params)),
+        _ => String::new()
+    }
+}
+
+// a subtree used as a truth value, which holds when it is positive
+fn condition(tree: &Tree) -> String {
+    let phrase = phrase(tree);
+    match phrase.strip_prefix("whether ") {
+        Some(condition) => condition.to_string(),
+        None => format!("{} is positive", phrase)
+    }
+}
+
+// "a", "a and b", "a, b and c"
+fn list(trees: &[Tree]) -> String {
+    let phrases: Vec<String> = trees.iter().map(phrase).collect();
+    match phrases.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, init)) => format!("{} and {}", init.join(", "), last),
+        None => String::new()
+    }
+}
+
+
+#[cfg(test)]
+mod explain_should {
+    use super::*;
+
+    #[test]
+    fn explain_arithmetic() {
+        assert_eq!(explain("1 + 2").unwrap(), "add 1 and 2");
+        assert_eq!(explain("(1+2)*3").unwrap(), "multiply the sum of 1 and 2 by 3");
+        assert_eq!(explain("x - -y / 2").unwrap(), "subtract the negation of the quotient of y and 2 from x");
+    }
+
+    #[test]
+    fn explain_functions_and_conditions() {
+        assert_eq!(explain("max(a, b, 1)").unwrap(), "call max with a, b and 1");
+        assert_eq!(explain("if(x >= 0, x, -x)").unwrap(), "take x if x is at least 0, otherwise the negation of x");
+        assert_eq!(explain("a < 1 && b").unwrap(), "check whether a is less than 1 and b is positive");
+        assert_eq!(explain("pi()").unwrap(), "take pi");
+        assert_eq!(explain("!(a < 1)").unwrap(), "check whether it is not true that a is less than 1");
+    }
+}
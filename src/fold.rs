@@ -0,0 +1,99 @@
+use super::{Error, ErrorKind, Expr, FuncExpr, Position, valid_operator};
+
+/// Computes a value for each kind of node from the values of its children,
+/// which lets callers reuse the parser with their own evaluation. Operators
+/// are passed in their canonical spelling, e.g. `=` for `==`.
+pub trait NodeFolder<T> {
+    fn number(&self, text: &str, pos: Position) -> Result<T, Error>;
+    fn variable(&self, name: &str, pos: Position) -> Result<T, Error>;
+    fn func(&self, name: &str, pos: Position, params: Vec<T>) -> Result<T, Error>;
+    fn unary(&self, operator: &str, at: u32, value: T) -> Result<T, Error>;
+    fn binary(&self, left: T, operator: &str, at: u32, right: T) -> Result<T, Error>;
+    // `a ? b : c` is folded as `if(a, b, c)` unless the folder tells them apart
+    fn ternary(&self, condition: T, at: u32, then: T, otherwise: T) -> Result<T, Error> {
+        self.func("if", Position { at, len: 1 }, vec![condition, then, otherwise])
+    }
+    // indexed variables are rare, so folders that do not expect them need not handle them
+    fn index(&self, base: &str, pos: Position, _index: T) -> Result<T, Error> {
+        Err(Error {
+            error: format!("Indexed variable '{}' is not supported here", base),
+            at: pos.at,
+            kind: ErrorKind::Other
+        })
+    }
+}
+
+/// Folds the tree bottom-up; `source` must be the string `expr` was parsed from.
+/// Every child is folded before its parent, so unlike `evaluate` both branches
+/// of an `if` are visited.
+pub fn fold<T>(expr: &Expr, source: &str, f: &impl NodeFolder<T>) -> Result<T, Error> {
+    match expr {
+        Expr::Number(pos) => f.number(&source[pos.to_range()], *pos),
+        Expr::Variable(pos) => f.variable(&source[pos.to_range()], *pos),
+        Expr::Ternary(ternary) => {
+            let condition = fold(&ternary.condition, source, f)?;
+            let then = fold(&ternary.then, source, f)?;
+            let otherwise = fold(&ternary.otherwise, source, f)?;
+            f.ternary(condition, ternary.at, then, otherwise)
+        }
+        Expr::Index(element) => f.index(&source[element.base.to_range()], element.base, fold(&element.index, source, f)?),
+        Expr::Func(func) => {
+            let FuncExpr { name, params } = &**func;
+            let params = params.iter().map(|param| fold(param, source, f)).collect::<Result<Vec<T>, Error>>()?;
+            f.func(&source[name.to_range()], *name, params)
+        }
+        Expr::Unary{ expr, operator_ix, at } => {
+            let operator = valid_operator(*operator_ix, *at)?;
+            f.unary(&operator.canonical().to_string(), *at, fold(expr, source, f)?)
+        }
+        Expr::Binary(bin) => {
+            let operator = valid_operator(bin.operator_ix, bin.at)?;
+            let left = fold(&bin.left, source, f)?;
+            let right = fold(&bin.right, source, f)?;
+            f.binary(left, &operator.canonical().to_string(), bin.at, right)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod fold_should {
+    use super::*;
+    use crate::{tokenizer, parser};
+
+    struct Multiplications;
+
+    impl NodeFolder<u32> for Multiplications {
+        fn number(&self, _text: &str, _pos: Position) -> Result<u32, Error> {
+            Ok(0)
+        }
+
+        fn variable(&self, _name: &str, _pos: Position) -> Result<u32, Error> {
+            Ok(0)
+        }
+
+        fn func(&self, _name: &str, _pos: Position, params: Vec<u32>) -> Result<u32, Error> {
+            Ok(params.iter().sum())
+        }
+
+        fn unary(&self, _operator: &str, _at: u32, value: u32) -> Result<u32, Error> {
+            Ok(value)
+        }
+
+        fn binary(&self, left: u32, operator: &str, _at: u32, right: u32) -> Result<u32, Error> {
+            Ok(left + right + (operator == "*") as u32)
+        }
+    }
+
+    fn count(expression: &str) -> u32 {
+        let expr = parser::parse(&mut tokenizer::Tokens::new(expression)).unwrap();
+        fold(&expr, expression, &Multiplications).unwrap()
+    }
+
+    #[test]
+    fn count_multiplications() {
+        assert_eq!(count("1 + 2"), 0);
+        assert_eq!(count("a * b × -(c * 2) / d"), 3);
+        assert_eq!(count("max(1 * 2, if(x, 3 * 4, 0))"), 2);
+    }
+}
@@ -0,0 +1,133 @@
+use super::{Error, ErrorKind, evaluate_f64};
+
+/// Evaluates the expression in f64 and formats the result with a printf-style spec:
+/// `%f`, `%e` or `%g`, optionally with a precision as in `%.3e`. The precision
+/// defaults to 6 like in C.
+pub fn evaluate_format(expression: &str, spec: &str) -> Result<String, Error> {
+    let (precision, conversion) = parse_spec(spec).ok_or_else(|| Error {
+        error: format!("Unsupported format spec '{}'", spec),
+        at: 0,
+        kind: ErrorKind::Other
+    })?;
+    let value = evaluate_f64(expression)?;
+    if !value.is_finite() {
+        return Ok(if value.is_nan() { "nan".to_string() } else if value > 0.0 { "inf".to_string() } else { "-inf".to_string() });
+    }
+    Ok(match conversion {
+        'f' => format!("{:.*}", precision, value),
+        'e' => scientific(value, precision),
+        _ => general(value, precision)
+    })
+}
+
+/// Evaluates the expression in f64 and formats the result with `decimals`
+/// fraction digits and commas between groups of thousands, e.g. `1,234,567.89`.
+pub fn evaluate_to_grouped_string(expression: &str, decimals: usize) -> Result<String, Error> {
+    let value = evaluate_f64(expression)?;
+    if !value.is_finite() {
+        return Ok(format!("{:.*}", decimals, value));
+    }
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (integer, fraction) = formatted.split_at(formatted.find('.').unwrap_or(formatted.len()));
+    let mut grouped = String::new();
+    for (ix, digit) in integer.chars().enumerate() {
+        if ix > 0 && (integer.len() - ix) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    // no sign for values that round to zero
+    let sign = if value < 0.0 && formatted.chars().any(|ch| ch.is_ascii_digit() && ch != '0') {"-"} else {""};
+    Ok(format!("{}{}{}", sign, grouped, fraction))
+}
+
+// precision and conversion character of e.g. "%.2e"
+fn parse_spec(spec: &str) -> Option<(usize, char)> {
+    let spec = spec.strip_prefix('%')?;
+    let conversion = spec.chars().last().filter(|ch| matches!(ch, 'f' | 'e' | 'g'))?;
+    let precision = &spec[.. spec.len() - 1];
+    if precision.is_empty() {
+        return Some((6, conversion));
+    }
+    let digits = precision.strip_prefix('.')?;
+    if digits.is_empty() || !digits.chars().all(|ch| ch.is_ascii_digit()) {
+        return None;
+    }
+    Some((digits.parse().ok()?, conversion))
+}
+
+// C writes the exponent with a sign and at least two digits: 1.23e+04
+fn scientific(value: f64, precision: usize) -> String {
+    let (mantissa, exponent) = split_exponent(value, precision);
+    format!("{}e{}{:02}", mantissa, if exponent < 0 {'-'} else {'+'}, exponent.abs())
+}
+
+fn split_exponent(value: f64, precision: usize) -> (String, i32) {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_at(formatted.find('e').unwrap());
+    (mantissa.to_string(), exponent[1..].parse().unwrap())
+}
+
+// scientific for very small or large exponents, fixed otherwise, without trailing zeros
+fn general(value: f64, precision: usize) -> String {
+    let precision = precision.max(1);
+    let (mantissa, exponent) = split_exponent(value, precision - 1);
+    if exponent < -4 || exponent >= precision as i32 {
+        format!("{}e{}{:02}", trim_zeros(&mantissa), if exponent < 0 {'-'} else {'+'}, exponent.abs())
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        trim_zeros(&format!("{:.*}", decimals, value)).to_string()
+    }
+}
+
+fn trim_zeros(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
+
+
+#[cfg(test)]
+mod format_should {
+    use super::*;
+
+    #[test]
+    fn format_fixed() {
+        assert_eq!(evaluate_format("1/4", "%.3f").unwrap(), "0.250");
+        assert_eq!(evaluate_format("2", "%f").unwrap(), "2.000000");
+        assert_eq!(evaluate_format("16777217", "%.1f").unwrap(), "16777217.0");
+        assert_eq!(evaluate_format("0.1", "%.12e").unwrap(), "1.000000000000e-01");
+    }
+
+    #[test]
+    fn format_scientific() {
+        assert_eq!(evaluate_format("12345.0", "%.2e").unwrap(), "1.23e+04");
+        assert_eq!(evaluate_format("0.5", "%e").unwrap(), "5.000000e-01");
+    }
+
+    #[test]
+    fn format_general() {
+        assert_eq!(evaluate_format("0.0001", "%g").unwrap(), "0.0001");
+        assert_eq!(evaluate_format("100000 * 10", "%g").unwrap(), "1e+06");
+        assert_eq!(evaluate_format("1/3", "%.3g").unwrap(), "0.333");
+        assert_eq!(evaluate_format("0", "%g").unwrap(), "0");
+    }
+
+    #[test]
+    fn group_thousands() {
+        assert_eq!(evaluate_to_grouped_string("1234567.891", 2).unwrap(), "1,234,567.89");
+        assert_eq!(evaluate_to_grouped_string("0 - 1234.6", 0).unwrap(), "-1,235");
+        assert_eq!(evaluate_to_grouped_string("999.999", 2).unwrap(), "1,000.00");
+        assert_eq!(evaluate_to_grouped_string("123", 1).unwrap(), "123.0");
+        assert_eq!(evaluate_to_grouped_string("0 - 0.001", 2).unwrap(), "0.00");
+    }
+
+    #[test]
+    fn error_on_unsupported_spec() {
+        for spec in &["%d", "%.e", "%5f", ".2f", "%"] {
+            assert_matches!(evaluate_format("1", spec), Err(..));
+        }
+    }
+}
@@ -0,0 +1,77 @@
+/// Parses a literal with a radix prefix: a hexadecimal integer like `0xFF`,
+/// a binary one like `0b1010`, or a hexadecimal float.
+pub(crate) fn parse_prefixed(text: &str) -> Option<f64> {
+    let (radix, digits) = match text.get(..2)? {
+        "0x" | "0X" if text.contains(['.', 'p', 'P']) => return parse_hex_float(text),
+        "0x" | "0X" => (16, &text[2..]),
+        "0b" | "0B" => (2, &text[2..]),
+        _ => return None
+    };
+    // from_str_radix would accept a sign
+    if digits.is_empty() || !digits.chars().all(|ch| ch.is_digit(radix)) {
+        return None;
+    }
+    u64::from_str_radix(digits, radix).ok().map(|value| value as f64)
+}
+
+/// Parses a C99 hexadecimal float like `0x1.8p3`, which is exactly `1.5 * 2^3`.
+/// The binary exponent after `p` is required. Digits beyond what an `f64`
+/// holds are truncated.
+pub(crate) fn parse_hex_float(text: &str) -> Option<f64> {
+    let rest = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))?;
+    let (mantissa, exponent) = rest.split_once(['p', 'P'])?;
+    let (int_digits, frac_digits) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int_digits.is_empty() && frac_digits.is_empty() {
+        return None;
+    }
+    let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let mut exponent = exponent.parse::<i32>().ok()?;
+    let mut bits = 0u64;
+    for (ix, ch) in int_digits.chars().chain(frac_digits.chars()).enumerate() {
+        let digit = ch.to_digit(16)? as u64;
+        if ix >= int_digits.len() {
+            exponent = exponent.checked_sub(4)?;
+        }
+        if bits >> 60 == 0 {
+            bits = bits << 4 | digit;
+        } else {
+            exponent = exponent.checked_add(4)?;
+        }
+    }
+    // the mantissa rounds to an f64 once, the power of two scales it exactly
+    Some(bits as f64 * 2f64.powi(exponent))
+}
+
+
+#[cfg(test)]
+mod hex_should {
+    use super::*;
+
+    #[test]
+    fn parse_hex_floats() {
+        assert_eq!(parse_hex_float("0x1p4"), Some(16.0));
+        assert_eq!(parse_hex_float("0x1.8p1"), Some(3.0));
+        assert_eq!(parse_hex_float("0X.8P-1"), Some(0.25));
+        assert_eq!(parse_hex_float("0x1.fffffep+127"), Some(f32::MAX as f64));
+    }
+
+    #[test]
+    fn parse_prefixed_integers() {
+        assert_eq!(parse_prefixed("0xFF"), Some(255.0));
+        assert_eq!(parse_prefixed("0b1010"), Some(10.0));
+        assert_eq!(parse_prefixed("0x1.8p1"), Some(3.0));
+        for text in &["0xGG", "0x", "0b102", "0x+1", "10", "0x10000000000000000"] {
+            assert_eq!(parse_prefixed(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn reject_malformed_hex_floats() {
+        for text in &["0x1.8", "0xp1", "0x1p", "0x1.8.1p1", "0x1gp1", "0x1p+", "1p4"] {
+            assert_eq!(parse_hex_float(text), None, "{}", text);
+        }
+    }
+}
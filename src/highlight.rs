@@ -0,0 +1,103 @@
+use super::{IdentKind, classify_identifier, tokenizer};
+use tokenizer::Token;
+use std::ops::Range;
+
+/// How a piece of the source is displayed by `highlight`.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub enum TokenClass {
+    Number,
+    Operator,
+    Paren,
+    Comma,
+    Function,
+    Variable,
+    Keyword,
+    /// Text the tokenizer rejected, e.g. a reserved character.
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+#[derive(Clone)]
+pub struct HighlightSpan {
+    pub range: Range<usize>,
+    pub kind: TokenClass
+}
+
+/// Splits the expression into classified spans for syntax highlighting.
+/// It only tokenizes, so it never fails: text the tokenizer rejects becomes
+/// an `Error` span and the rest is still classified. Whitespace is not covered.
+pub fn highlight(expression: &str) -> Vec<HighlightSpan> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let mut spans = vec![];
+    loop {
+        let skipped = &expression[tokens.offset() as usize ..];
+        let start = expression.len() - skipped.trim_start().len();
+        let (token, end) = match tokens.next() {
+            Some(token) => (token, tokens.offset() as usize),
+            None => break
+        };
+        let kind = match token {
+            Ok(Token::Number(_)) => TokenClass::Number,
+            Ok(Token::Operator { .. }) | Ok(Token::Question(_)) | Ok(Token::Colon(_)) => TokenClass::Operator,
+            Ok(Token::LParen(_)) | Ok(Token::RParen(_)) | Ok(Token::LBracket(_)) | Ok(Token::RBracket(_)) => TokenClass::Paren,
+            Ok(Token::Comma(_)) | Ok(Token::Newline(_)) => TokenClass::Comma,
+            Ok(Token::Str(pos)) => match classify_identifier(&expression[pos.to_range()]) {
+                IdentKind::Keyword(_) => TokenClass::Keyword,
+                _ if expression[end ..].trim_start().starts_with('(') => TokenClass::Function,
+                _ => TokenClass::Variable
+            },
+            Err(_) => TokenClass::Error
+        };
+        spans.push(HighlightSpan { range: start .. end, kind });
+    }
+    spans
+}
+
+
+#[cfg(test)]
+mod highlight_should {
+    use super::*;
+
+    fn classes(expression: &str) -> Vec<(&str, TokenClass)> {
+        highlight(expression).into_iter().map(|span| (&expression[span.range], span.kind)).collect()
+    }
+
+    #[test]
+    fn tell_functions_from_variables() {
+        assert_eq!(classes("sin(x) + 2"), vec![
+            ("sin", TokenClass::Function),
+            ("(", TokenClass::Paren),
+            ("x", TokenClass::Variable),
+            (")", TokenClass::Paren),
+            ("+", TokenClass::Operator),
+            ("2", TokenClass::Number)
+        ]);
+        assert_eq!(classes("if (a >= 1, b, 0) xor c"), vec![
+            ("if", TokenClass::Keyword),
+            ("(", TokenClass::Paren),
+            ("a", TokenClass::Variable),
+            (">=", TokenClass::Operator),
+            ("1", TokenClass::Number),
+            (",", TokenClass::Comma),
+            ("b", TokenClass::Variable),
+            (",", TokenClass::Comma),
+            ("0", TokenClass::Number),
+            (")", TokenClass::Paren),
+            ("xor", TokenClass::Operator),
+            ("c", TokenClass::Variable)
+        ]);
+    }
+
+    #[test]
+    fn keep_going_after_invalid_text() {
+        assert_eq!(classes("1 @ 1.2.3 + x"), vec![
+            ("1", TokenClass::Number),
+            ("@", TokenClass::Error),
+            ("1.2.3", TokenClass::Error),
+            ("+", TokenClass::Operator),
+            ("x", TokenClass::Variable)
+        ]);
+        assert!(highlight("").is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use super::operator;
+
+/// Reserved words of the language.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub enum Keyword {
+    Let, // assignment in a `Session`
+    Def, // function definition in a `Program`
+    If,  // evaluates only the taken branch
+    Xor,
+}
+
+/// What a name in an expression refers to.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub enum IdentKind {
+    Keyword(Keyword),
+    Constant,
+    BuiltinFunction,
+    Variable,
+}
+
+// called without arguments, like `pi()`
+const CONSTANTS: [&str; 3] = ["pi", "e", "tau"];
+
+const BUILTIN_FUNCTIONS: [&str; 32] = [
+    "add", "sub", "mul", "div", "approx", "dist", "sin_norm", "cos_norm", "tan_norm", "floor", "ceil", "round",
+    "sqrt", "ln", "asin", "min", "max", "sum", "product", "avg", "clamp", "clamp_side", "between", "wrap",
+    "abs", "sin", "cos", "tan", "log", "exp", "pow", "safe_div"
+];
+
+/// Classifies a name the way the evaluator treats it; anything that is not
+/// built in is a variable.
+pub fn classify_identifier(name: &str) -> IdentKind {
+    match name {
+        "let" => IdentKind::Keyword(Keyword::Let),
+        "def" => IdentKind::Keyword(Keyword::Def),
+        "if" => IdentKind::Keyword(Keyword::If),
+        _ if operator::is_keyword(name).is_some() => IdentKind::Keyword(Keyword::Xor),
+        _ if CONSTANTS.contains(&name) => IdentKind::Constant,
+        _ if BUILTIN_FUNCTIONS.contains(&name) => IdentKind::BuiltinFunction,
+        _ => IdentKind::Variable
+    }
+}
+
+
+#[cfg(test)]
+mod ident_should {
+    use super::*;
+
+    #[test]
+    fn classify_names() {
+        assert_eq!(classify_identifier("if"), IdentKind::Keyword(Keyword::If));
+        assert_eq!(classify_identifier("let"), IdentKind::Keyword(Keyword::Let));
+        assert_eq!(classify_identifier("xor"), IdentKind::Keyword(Keyword::Xor));
+        assert_eq!(classify_identifier("pi"), IdentKind::Constant);
+        assert_eq!(classify_identifier("sin_norm"), IdentKind::BuiltinFunction);
+        assert_eq!(classify_identifier("myvar"), IdentKind::Variable);
+        assert_eq!(classify_identifier("Pi"), IdentKind::Variable);
+    }
+}
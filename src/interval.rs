@@ -0,0 +1,332 @@
+use super::{Error, ErrorKind, Expr, FuncExpr, Position, tokenizer, parser, hex, without_separators, valid_operator, misplaced_operator};
+
+// Rust has no directed rounding modes, so each operation is carried out in f64
+// together with the exact sign of its rounding error, and the f64 result is then
+// rounded outward to the neighbouring f32 values.
+
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+struct Interval {
+    lo: f32,
+    hi: f32
+}
+
+impl Interval {
+    fn point(value: f32) -> Interval {
+        Interval { lo: value, hi: value }
+    }
+
+    // smallest interval covering both
+    fn hull(self, other: Interval) -> Interval {
+        Interval { lo: self.lo.min(other.lo), hi: self.hi.max(other.hi) }
+    }
+}
+
+/// Evaluates the expression in interval arithmetic, returning `(lo, hi)` bounds
+/// guaranteed to contain the exact result despite f32 rounding. Operations whose
+/// result is exactly representable yield a degenerate interval where `lo == hi`.
+/// Variables have no values here, so any variable is an unknown variable error.
+pub fn evaluate_interval(expression: &str) -> Result<(f32, f32), Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let Interval { lo, hi } = eval(&expr, expression)?;
+    Ok((lo, hi))
+}
+
+fn eval(expr: &Expr, source: &str) -> Result<Interval, Error> {
+    match expr {
+        Expr::Number(pos) => {
+            let text = &source[pos.to_range()];
+            literal(text).ok_or_else(|| Error {
+                error: format!("Invalid number '{}'", text),
+                at: pos.at,
+                kind: ErrorKind::InvalidNumber
+            })
+        }
+        // there is nothing to take values from, so every variable is unknown
+        Expr::Variable(pos) => Err(unknown_variable(*pos, source)),
+        Expr::Index(element) => Err(unknown_variable(element.base, source)),
+        Expr::Unary{ expr, operator_ix, at } => {
+            let operator = valid_operator(*operator_ix, *at)?;
+            let value = eval(expr, source)?;
+            match operator.char1 {
+                '+' => Ok(value),
+                '-' => Ok(Interval { lo: -value.hi, hi: -value.lo }),
+                '!' => Ok(compare(value.hi <= 0.0, value.lo > 0.0)),
+                _ => Err(misplaced_operator(operator, *at))
+            }
+        }
+        Expr::Binary(bin) => {
+            let left = eval(&bin.left, source)?;
+            let right = eval(&bin.right, source)?;
+            binary(bin.operator_ix, bin.at, left, right)
+        }
+        Expr::Func(func) => {
+            let FuncExpr { name, params } = &**func;
+            match &source[name.to_range()] {
+                // the f64 constants are within an f64 ulp of the real ones, far
+                // too close to change which f32 neighbours bracket them
+                "pi" => Ok(literal_f64(std::f64::consts::PI)),
+                "e" => Ok(literal_f64(std::f64::consts::E)),
+                "tau" => Ok(literal_f64(std::f64::consts::TAU)),
+                "if" if params.len() == 3 => choose(&params[0], &params[1], &params[2], source),
+                other => Err(unsupported(other, *name))
+            }
+        }
+        Expr::Ternary(ternary) => choose(&ternary.condition, &ternary.then, &ternary.otherwise, source)
+    }
+}
+
+// both branches when the condition could go either way
+fn choose(condition: &Expr, then: &Expr, otherwise: &Expr, source: &str) -> Result<Interval, Error> {
+    let condition = eval(condition, source)?;
+    if condition.lo > 0.0 {
+        eval(then, source)
+    } else if condition.hi <= 0.0 {
+        eval(otherwise, source)
+    } else {
+        Ok(eval(then, source)?.hull(eval(otherwise, source)?))
+    }
+}
+
+fn unknown_variable(pos: Position, source: &str) -> Error {
+    Error {
+        error: format!("Unknown variable '{}'", &source[pos.to_range()]),
+        at: pos.at,
+        kind: ErrorKind::UnknownVariable
+    }
+}
+
+fn unsupported(name: &str, pos: Position) -> Error {
+    Error {
+        error: format!("Function '{}' is not supported in interval evaluation", name),
+        at: pos.at,
+        kind: ErrorKind::Other
+    }
+}
+
+fn binary(operator_ix: u8, at: u32, a: Interval, b: Interval) -> Result<Interval, Error> {
+    let operator = valid_operator(operator_ix, at)?;
+    if !operator.infix() {
+        return Err(misplaced_operator(operator, at));
+    }
+    if operator.word == Some("xor") {
+        return Ok(match (truth(a), truth(b)) {
+            (Some(x), Some(y)) => compare(x != y, x == y),
+            _ => compare(false, false)
+        });
+    }
+    let value = match operator.char1 {
+        '&' => compare(truth(a) == Some(true) && truth(b) == Some(true), truth(a) == Some(false) || truth(b) == Some(false)),
+        '|' => compare(truth(a) == Some(true) || truth(b) == Some(true), truth(a) == Some(false) && truth(b) == Some(false)),
+        '+' => add(a, b),
+        '-' => add(a, Interval { lo: -b.hi, hi: -b.lo }),
+        '*' => corners(a, b, |x, y| (x * y, 0.0)), // f32 products are exact in f64
+        '/' if b.lo <= 0.0 && b.hi >= 0.0 => Interval { lo: f32::NEG_INFINITY, hi: f32::INFINITY },
+        '/' => corners(a, b, |x, y| {
+            let quotient = x / y;
+            // x - quotient * y is exact, its sign tells where the true quotient lies
+            let residual = (-quotient).mul_add(y, x);
+            (quotient, residual * y.signum())
+        }),
+        '%' if b.lo <= 0.0 && b.hi >= 0.0 => Interval { lo: f32::NEG_INFINITY, hi: f32::INFINITY },
+        '%' if a.lo == a.hi && b.lo == b.hi => Interval::point(a.lo % b.lo), // the remainder is exact
+        // powf is not correctly rounded, so the bounds are widened by a step each way;
+        // for positive bases the extremes lie at the corners
+        '^' if a.lo > 0.0 => {
+            let bounds = corners(a, b, |x, y| (x.powf(y), 0.0));
+            Interval { lo: bounds.lo.next_down(), hi: bounds.hi.next_up() }
+        }
+        '^' => Interval { lo: f32::NEG_INFINITY, hi: f32::INFINITY },
+        // takes the sign of a and is smaller than both |a| and |b|
+        '%' => {
+            let limit = b.lo.abs().max(b.hi.abs());
+            Interval { lo: a.lo.min(0.0).max(-limit), hi: a.hi.max(0.0).min(limit) }
+        }
+        '>' if operator.char2 == Some('=') => compare(b.hi <= a.lo, b.lo > a.hi),
+        '<' if operator.char2 == Some('=') => compare(a.hi <= b.lo, a.lo > b.hi),
+        '>' => compare(a.lo > b.hi, a.hi <= b.lo),
+        '<' => compare(a.hi < b.lo, a.lo >= b.hi),
+        '=' => compare(a.lo == a.hi && a == b, a.hi < b.lo || b.hi < a.lo),
+        '!' => compare(a.hi < b.lo || b.hi < a.lo, a.lo == a.hi && a == b),
+        _ => return Err(misplaced_operator(operator, at))
+    };
+    Ok(value)
+}
+
+fn add(a: Interval, b: Interval) -> Interval {
+    let (lo, lo_err) = two_sum(a.lo as f64, b.lo as f64);
+    let (hi, hi_err) = two_sum(a.hi as f64, b.hi as f64);
+    Interval { lo: down(lo, lo_err), hi: up(hi, hi_err) }
+}
+
+// applies op to every pair of bounds; op returns the f64 result and its error
+fn corners(a: Interval, b: Interval, op: impl Fn(f64, f64) -> (f64, f64)) -> Interval {
+    let mut lo = f32::INFINITY;
+    let mut hi = f32::NEG_INFINITY;
+    for &x in &[a.lo, a.hi] {
+        for &y in &[b.lo, b.hi] {
+            let (value, err) = op(x as f64, y as f64);
+            lo = lo.min(down(value, err));
+            hi = hi.max(up(value, err));
+        }
+    }
+    Interval { lo, hi }
+}
+
+fn compare(always: bool, never: bool) -> Interval {
+    if always {
+        Interval::point(1.0)
+    } else if never {
+        Interval::point(0.0)
+    } else {
+        Interval { lo: 0.0, hi: 1.0 }
+    }
+}
+
+// None when the interval straddles the truthiness threshold
+fn truth(value: Interval) -> Option<bool> {
+    if value.lo > 0.0 {
+        Some(true)
+    } else if value.hi <= 0.0 {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn literal(text: &str) -> Option<Interval> {
+    let text = &without_separators(text);
+    // the f64 parse is closer to the literal than any f32 is, so rounding it
+    // outward brackets the literal itself
+    let parse = |number: &str| hex::parse_prefixed(number).or_else(|| number.parse::<f64>().ok());
+    match text.strip_suffix('%') {
+        Some(percent) => parse(percent).map(|value| literal_f64(value / 100.0)),
+        None => parse(text).map(literal_f64)
+    }
+}
+
+fn literal_f64(precise: f64) -> Interval {
+    Interval { lo: down(precise, 0.0), hi: up(precise, 0.0) }
+}
+
+// error-free addition: a + b == sum + err exactly
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_virtual = sum - a;
+    let err = (a - (sum - b_virtual)) + (b - b_virtual);
+    (sum, if err.is_nan() { 0.0 } else { err })
+}
+
+// largest f32 not above value + err, where only the sign of err matters
+fn down(value: f64, err: f64) -> f32 {
+    let rounded = value as f32;
+    if (rounded as f64) > value || ((rounded as f64) == value && err < 0.0) {
+        rounded.next_down()
+    } else {
+        rounded
+    }
+}
+
+// smallest f32 not below value + err, where only the sign of err matters
+fn up(value: f64, err: f64) -> f32 {
+    let rounded = value as f32;
+    if (rounded as f64) < value || ((rounded as f64) == value && err > 0.0) {
+        rounded.next_up()
+    } else {
+        rounded
+    }
+}
+
+
+#[cfg(test)]
+mod interval_should {
+    use super::*;
+    use crate::operator;
+
+    #[test]
+    fn straddle_inexact_division() {
+        let (lo, hi) = evaluate_interval("1.0/3.0").unwrap();
+        assert!(lo < hi);
+        assert!((lo as f64) < 1.0 / 3.0 && 1.0 / 3.0 < (hi as f64));
+        assert_eq!(hi, lo.next_up());
+    }
+
+    #[test]
+    fn be_degenerate_for_exact_operations() {
+        assert_eq!(evaluate_interval("1 + 2 * 3").unwrap(), (7.0, 7.0));
+        assert_eq!(evaluate_interval("0.5 * 4 - 1/4").unwrap(), (1.75, 1.75));
+    }
+
+    #[test]
+    fn bound_inexact_literals_and_sums() {
+        let (lo, hi) = evaluate_interval("0.1").unwrap();
+        assert!((lo as f64) < 0.1 && 0.1 < (hi as f64));
+        let (lo, hi) = evaluate_interval("16777216 + 1").unwrap();
+        assert_eq!((lo, hi), (16777216.0, 16777218.0));
+    }
+
+    #[test]
+    fn bound_constants() {
+        let (lo, hi) = evaluate_interval("pi()").unwrap();
+        assert!((lo as f64) < std::f64::consts::PI && std::f64::consts::PI < (hi as f64));
+        let (lo, hi) = evaluate_interval("e()").unwrap();
+        assert!((lo as f64) < std::f64::consts::E && std::f64::consts::E < (hi as f64));
+    }
+
+    #[test]
+    fn widen_undecided_comparisons() {
+        assert_eq!(evaluate_interval("1/3 > 0.3").unwrap(), (1.0, 1.0));
+        assert_eq!(evaluate_interval("0.1 * 3 = 0.3").unwrap(), (0.0, 1.0));
+    }
+
+    #[test]
+    fn handle_xor() {
+        assert_eq!(evaluate_interval("(1 > 0) xor (0 > 1)").unwrap(), (1.0, 1.0));
+        assert_eq!(evaluate_interval("(0.1 * 3 = 0.3) xor 1").unwrap(), (0.0, 1.0));
+    }
+
+    #[test]
+    fn handle_logic_operators() {
+        assert_eq!(evaluate_interval("1/3 > 0.3 && 0.1 < 1").unwrap(), (1.0, 1.0));
+        assert_eq!(evaluate_interval("(0.1 * 3 = 0.3) || 0").unwrap(), (0.0, 1.0));
+        assert_eq!(evaluate_interval("(0.1 * 3 = 0.3) || 1").unwrap(), (1.0, 1.0));
+    }
+
+    #[test]
+    fn bound_remainders() {
+        assert_eq!(evaluate_interval("7 % 3").unwrap(), (1.0, 1.0));
+        let (lo, hi) = evaluate_interval("0.1 % 1").unwrap();
+        assert!(lo <= 0.0 && 0.1 < (hi as f64) && hi < 1.0);
+        assert_eq!(evaluate_interval("1 % 0").unwrap(), (f32::NEG_INFINITY, f32::INFINITY));
+    }
+
+    #[test]
+    fn bound_powers() {
+        let (lo, hi) = evaluate_interval("2 ^ 0.5").unwrap();
+        assert!((lo as f64) < std::f64::consts::SQRT_2 && std::f64::consts::SQRT_2 < (hi as f64));
+        let (lo, hi) = evaluate_interval("(1 + 1) ^ 3").unwrap();
+        assert!(lo < 8.0 && 8.0 < hi);
+    }
+
+    #[test]
+    fn error_on_unsupported_function() {
+        assert_matches!(evaluate_interval("dist(1, 2)"), Err(..));
+    }
+
+    #[test]
+    fn error_on_unknown_variable() {
+        let error = evaluate_interval("x * 2").unwrap_err();
+        assert_eq!((error.error.as_str(), error.at, error.kind), ("Unknown variable 'x'", 0, ErrorKind::UnknownVariable));
+        assert_eq!(evaluate_interval("1 + v[0]").unwrap_err().at, 4);
+    }
+
+    #[test]
+    fn error_on_misplaced_operator() {
+        let one = || Expr::Number(Position { at: 1, len: 1 });
+        let expr = Expr::Unary { expr: Box::new(one()), operator_ix: operator::find("*").unwrap(), at: 0 };
+        assert_eq!(eval(&expr, "*1").unwrap_err().error, "Operator '*' cannot be used as a prefix");
+        let expr = Expr::Binary(Box::new(crate::BinaryExpr { left: one(), right: one(), operator_ix: operator::find("!").unwrap(), at: 0 }));
+        assert_eq!(eval(&expr, "!1").unwrap_err().kind, ErrorKind::UnexpectedToken);
+    }
+}
@@ -0,0 +1,43 @@
+use super::{Error, Context, tokenizer, parser, eval_expr};
+use std::cell::Cell;
+
+/// Evaluates the expression taking variables from the fields of a JSON object.
+/// A variable without a field, or whose field is not a number, is an error.
+pub fn evaluate_with_json(expression: &str, json: &serde_json::Value) -> Result<f32, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    // the evaluator only learns that a variable has no value, this tells it was not a number
+    let not_a_number = Cell::new(None);
+    let resolve = |name: &str| {
+        let field = json.get(name)?;
+        if !field.is_number() {
+            not_a_number.set(Some(name.to_string()));
+        }
+        field.as_f64().map(|value| value as f32)
+    };
+    eval_expr(&expr, &Context::new(expression, &resolve)).map_err(|error| match not_a_number.take() {
+        Some(name) => Error { error: format!("JSON field '{}' is not a number", name), ..error },
+        None => error
+    })
+}
+
+
+#[cfg(test)]
+mod json_should {
+    use super::*;
+
+    #[test]
+    fn resolve_variables_from_fields() {
+        let json = serde_json::json!({ "price": 12.5, "quantity": 4, "discount": 0.1 });
+        assert_eq!(evaluate_with_json("price * quantity * (1 - discount)", &json).unwrap(), 45.0);
+    }
+
+    #[test]
+    fn error_on_missing_or_non_numeric_fields() {
+        let json = serde_json::json!({ "name": "widget", "price": 3 });
+        let error = evaluate_with_json("price + name", &json).unwrap_err();
+        assert_eq!(error.error, "JSON field 'name' is not a number");
+        assert_eq!(error.at, 8);
+        assert_eq!(evaluate_with_json("price + tax", &json).unwrap_err().error, "Unknown variable 'tax'");
+    }
+}
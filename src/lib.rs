@@ -5,149 +5,2197 @@ extern crate matches;
 mod tokenizer;
 mod parser;
 mod operator;
+mod arena;
+mod interval;
+mod session;
+mod parens;
+mod complexity;
+mod format;
+mod bound;
+mod ident;
+mod diff;
+mod fold;
+mod program;
+mod spans;
+mod explain;
+mod types;
+mod diagnostics;
+mod hex;
+mod highlight;
+mod literals;
+mod memo;
+mod resolved;
+mod print;
+mod constant;
+#[cfg(any(feature = "units", feature = "rational", feature = "complex", feature = "vectors"))]
+mod numeric;
+mod double;
+mod real;
+#[cfg(feature = "units")]
+mod units;
+#[cfg(feature = "rational")]
+mod rational;
+#[cfg(feature = "complex")]
+mod complex;
+#[cfg(feature = "serde_json")]
+mod json;
+#[cfg(feature = "vectors")]
+mod vector;
+
+pub use arena::{ExprArena, Node, NodeId};
+pub use interval::evaluate_interval;
+pub use session::Session;
+pub use parens::redundant_parens;
+pub use complexity::complexity_score;
+pub use format::{evaluate_format, evaluate_to_grouped_string};
+pub use bound::BoundContext;
+pub use double::evaluate_f64;
+pub use ident::{IdentKind, Keyword, classify_identifier};
+pub use diff::{TreeDiff, diff};
+pub use fold::{NodeFolder, fold};
+pub use program::Program;
+pub use spans::{NodeInfo, NodeKind, node_at};
+pub use explain::explain;
+pub use types::{ValueType, result_type};
+pub use diagnostics::{Diagnostic, DiagnosticKind, evaluate_with_diagnostics, evaluate_with_diagnostics_and_options};
+pub use literals::literals;
+pub use highlight::{HighlightSpan, TokenClass, highlight};
+pub use memo::MemoEvaluator;
+pub use resolved::{ResolvedExpr, resolve};
+pub use constant::const_eval;
+pub use operator::OperatorAliases;
+pub use tokenizer::{Token, Tokens, tokenize_with_spans};
+#[cfg(feature = "units")]
+pub use units::{Quantity, evaluate_units};
+#[cfg(feature = "rational")]
+pub use rational::evaluate_rational;
+#[cfg(feature = "complex")]
+pub use complex::{Complex, evaluate_complex};
+#[cfg(feature = "serde_json")]
+pub use json::evaluate_with_json;
+#[cfg(feature = "vectors")]
+pub use vector::{Value, evaluate_vector};
+
+use std::borrow::Borrow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::hash::Hash;
+use real::Real;
 
 #[derive(Debug, PartialEq)]
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Position {
     pub at: u32,
     pub len: u16
 }
 
 impl Position {
-    fn to_range(&self) -> std::ops::Range<usize> {
+    /// The byte range of the source this position covers.
+    pub fn to_range(self) -> std::ops::Range<usize> {
         let start = self.at as usize;
         let end = start + self.len as usize;
         start .. end
     }
+
+    /// The range in characters rather than bytes, for UIs that count characters.
+    pub fn char_range(self, source: &str) -> std::ops::Range<usize> {
+        let start = char_offset(source, self.at) as usize;
+        let end = start + char_offset(&source[self.at as usize ..], self.len as u32) as usize;
+        start .. end
+    }
 }
 
-#[derive(Debug)]
+// the number of characters before the byte offset
+fn char_offset(source: &str, at: u32) -> u32 {
+    source.char_indices().take_while(|&(ix, _)| ix < at as usize).count() as u32
+}
+
+/// A parsed expression. Positions refer to the source it was parsed from and
+/// `operator_ix` identifies an operator, see `operator_symbol`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Number(Position),
     Variable(Position),
     Func ( Box<FuncExpr> ),
-    Unary{ expr: Box<Expr>, operator_ix: u8 },
-    Binary(Box<BinaryExpr>)
+    Unary{ expr: Box<Expr>, operator_ix: u8, at: u32 },
+    Binary(Box<BinaryExpr>),
+    /// An element of an indexed variable, `base[index]`.
+    Index(Box<IndexExpr>),
+    /// `condition ? then : otherwise`, which evaluates like `if`.
+    Ternary(Box<TernaryExpr>)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncExpr {
-    name: Position,
-    params: Vec<Expr>
+    pub name: Position,
+    pub params: Vec<Expr>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexExpr {
+    pub base: Position,
+    pub index: Expr
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TernaryExpr {
+    pub condition: Expr,
+    pub at: u32, // of the '?'
+    pub then: Expr,
+    pub otherwise: Expr
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BinaryExpr {
-    left: Expr,
-    right: Expr,
-    operator_ix: u8
+    pub left: Expr,
+    pub right: Expr,
+    pub operator_ix: u8,
+    pub at: u32 // position of the operator
+}
+
+/// How the operator with the given index is written, e.g. `>=`.
+pub fn operator_symbol(operator_ix: u8) -> Option<String> {
+    operator::try_from(operator_ix).map(|operator| operator.to_string())
+}
+
+/// Options affecting how an expression is tokenized and parsed.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Binary operators must have whitespace on both sides, which rules out
+    /// ambiguous input like `1-2`. Prefix operators are not affected.
+    pub require_operator_spacing: bool,
+    /// Alternative operator spellings, see `OperatorAliases::default()`.
+    pub aliases: OperatorAliases,
+    /// Identifiers mixing letters of different scripts are errors, so that
+    /// a Cyrillic `раssword` cannot pass for `password`.
+    pub reject_mixed_script_identifiers: bool,
+    pub identifier_chars: IdentifierChars,
+    /// A built-in function name without a following `(` is an error instead
+    /// of a variable, which catches a forgotten `()`.
+    pub strict_parens: bool,
+}
+
+/// Decides which characters make up identifiers. Numbers are recognized
+/// before identifiers, so a leading digit always starts a number.
+/// A symbol such as `-` starts an identifier only where no operand comes
+/// before it and another identifier character follows, so `a - b` subtracts.
+#[derive(Debug, Clone, Copy)]
+pub struct IdentifierChars {
+    pub is_start: fn(char) -> bool,
+    pub is_continue: fn(char) -> bool,
+}
+
+impl Default for IdentifierChars {
+    // letters, digits and '_'
+    fn default() -> IdentifierChars {
+        let alphanumeric = |ch: char| ch.is_alphanumeric() || ch == '_';
+        IdentifierChars { is_start: alphanumeric, is_continue: alphanumeric }
+    }
+}
+
+/// Parses the expression into a tree that can be inspected or evaluated
+/// repeatedly, kept together with the source its positions refer to.
+pub fn parse(expression: &str) -> Result<SourcedExpr<'_>, Error> {
+    Parser::default().parse(expression)
+}
+
+/// Parses any number of expressions with the same settings, e.g.
+/// `Parser::new().max_depth(64).max_length(1000)`.
+#[derive(Debug, Clone, Default)]
+pub struct Parser {
+    options: ParseOptions,
+    max_length: Option<usize>,
+    max_depth: Option<u32>,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    pub fn options(self, options: ParseOptions) -> Parser {
+        Parser { options, ..self }
+    }
+
+    /// Longest expression, in bytes, that is parsed at all.
+    pub fn max_length(self, max_length: usize) -> Parser {
+        Parser { max_length: Some(max_length), ..self }
+    }
+
+    /// Deepest nesting of operations, calls and parentheses, checked while
+    /// parsing so that deep nesting fails before it can exhaust the stack.
+    pub fn max_depth(self, max_depth: u32) -> Parser {
+        Parser { max_depth: Some(max_depth), ..self }
+    }
+
+    pub fn parse<'a>(&self, expression: &'a str) -> Result<SourcedExpr<'a>, Error> {
+        let error = |error: String, at: u32| Err(Error { error, at, kind: ErrorKind::Other });
+        if let Some(max_length) = self.max_length.filter(|&max_length| expression.len() > max_length) {
+            return error(format!("Expression is longer than {} bytes", max_length), max_length as u32);
+        }
+        let mut tokens = tokenizer::Tokens::with_options(expression, self.options.clone());
+        let expr = parser::parse_with_limit(&mut tokens, &mut parser::BoxBuilder, self.max_depth.unwrap_or(u32::MAX))?;
+        Ok(SourcedExpr { expr, source: expression })
+    }
+}
+
+/// A parsed expression and the source it was parsed from, so it cannot be
+/// evaluated against the wrong string.
+#[derive(Debug, Clone)]
+pub struct SourcedExpr<'a> {
+    expr: Expr,
+    source: &'a str
+}
+
+impl<'a> SourcedExpr<'a> {
+    /// Evaluates the expression; any variable is reported as unknown.
+    pub fn eval(&self) -> Result<f32, Error> {
+        eval_expr(&self.expr, &Context::new(self.source, &|_| None))
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    pub fn into_expr(self) -> Expr {
+        self.expr
+    }
+}
+
+/// A compiled expression that owns its source, so it can be kept and evaluated
+/// many times with different variables without parsing again. With the `serde`
+/// feature it can be saved and loaded, keeping the positions valid.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expression {
+    expr: Expr,
+    source: String
+}
+
+impl Expression {
+    pub fn compile(expression: &str) -> Result<Expression, Error> {
+        let mut tokens = tokenizer::Tokens::new(expression);
+        Ok(Expression { expr: parser::parse(&mut tokens)?, source: expression.to_string() })
+    }
+
+    /// Evaluates the expression; any variable is reported as unknown.
+    pub fn eval(&self) -> Result<f32, Error> {
+        eval_expr(&self.expr, &Context::new(&self.source, &|_| None))
+    }
+
+    /// Evaluates the expression, taking variable values from `vars`.
+    pub fn eval_with<K>(&self, vars: &HashMap<K, f32>) -> Result<f32, Error>
+        where K: Borrow<str> + Hash + Eq {
+        let resolve = |name: &str| vars.get(name).copied();
+        eval_expr(&self.expr, &Context::new(&self.source, &resolve))
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
 }
 
+/// Evaluates a tree, e.g. one built by hand or taken out of a `SourcedExpr`;
+/// `expression` must be the string its positions refer to.
+pub fn eval(expr: &Expr, expression: &str) -> Result<f32, Error> {
+    eval_expr(expr, &Context::new(expression, &|_| None))
+}
 
+/// Evaluates an expression without variables; any variable is reported as unknown.
 pub fn evaluate(expression: &str) -> Result<f32, Error> {
+    evaluate_with(expression, &HashMap::<&str, f32>::new())
+}
+
+/// Evaluates the expression, taking variable values from `vars`. A variable
+/// missing from the map is an error pointing at it.
+pub fn evaluate_with<K>(expression: &str, vars: &HashMap<K, f32>) -> Result<f32, Error>
+    where K: Borrow<str> + Hash + Eq {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let resolve = |name: &str| vars.get(name).copied();
+    eval_expr(&expr, &Context::new(expression, &resolve))
+}
+
+/// Evaluates the expression asking `resolve` for the value of each variable:
+/// `resolve(name, None)` for a plain `name` and `resolve(name, Some(i))` for
+/// an element `name[i]`, whose index must evaluate to a non-negative integer.
+pub fn evaluate_with_resolver(expression: &str, resolve: &dyn Fn(&str, Option<usize>) -> Option<f32>) -> Result<f32, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let variable = |name: &str| resolve(name, None);
+    let element = |name: &str, index| resolve(name, Some(index));
+    eval_expr(&expr, &Context::new(expression, &variable).with_elements(&element))
+}
+
+/// Evaluates the expression with functions defined by the caller, which take
+/// precedence over built-in ones of the same name except `if`. Calls to names
+/// that are neither are errors. An error returned by a function is reported at the call.
+pub fn evaluate_with_funcs<K, F>(expression: &str, funcs: &HashMap<K, F>) -> Result<f32, Error>
+    where K: Borrow<str> + Hash + Eq, F: Fn(&[f32]) -> Result<f32, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let options = EvalOptions { reject_unknown_functions: true, ..EvalOptions::default() };
+    let funcs = FuncMap(funcs);
+    eval_expr(&expr, &Context::new(expression, &|_| None).with_options(options).with_functions(&funcs))
+}
+
+struct FuncMap<'a, K, F>(&'a HashMap<K, F>);
+
+impl<K, F> Functions for FuncMap<'_, K, F>
+    where K: Borrow<str> + Hash + Eq, F: Fn(&[f32]) -> Result<f32, Error> {
+    // 'if' only evaluates one branch, which a function of evaluated arguments cannot replace
+    fn contains(&self, name: &str) -> bool {
+        name != "if" && self.0.contains_key(name)
+    }
+
+    fn call(&self, name: &str, at: u32, args: &[f32]) -> Result<f32, Error> {
+        (self.0[name])(args).map_err(|error| Error { at, ..error })
+    }
+}
+
+/// An expression compiled by `compile_to_closure`.
+pub type CompiledFn = Box<dyn Fn(&HashMap<String, f32>) -> Result<f32, Error>>;
+
+/// Parses the expression once into a closure that evaluates it against the
+/// variables it is called with, for storing in structs or calling in hot loops.
+pub fn compile_to_closure(expression: &str) -> Result<CompiledFn, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let source = expression.to_string();
+    Ok(Box::new(move |vars| {
+        let resolve = |name: &str| vars.get(name).copied();
+        eval_expr(&expr, &Context::new(&source, &resolve))
+    }))
+}
+
+/// Parses a boolean expression in the single variable `var` once into a
+/// predicate for `retain` or `filter`, e.g. `x > 3`. Expressions that are not
+/// boolean, or that read other variables, are rejected here; a value for which
+/// evaluation fails does not pass.
+pub fn compile_predicate(expression: &str, var: &str) -> Result<impl Fn(f32) -> bool, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    expect_boolean(&expr, expression)?;
+    let mut free = vec![];
+    // only `var` has a value, so any other counter counts
+    collect_variables(&expr, expression, false, &mut free);
+    if let Some(other) = free.iter().find(|name| *name != var) {
+        let at = highlight(expression).into_iter()
+            .find(|span| span.kind == TokenClass::Variable && expression[span.range.clone()] == **other)
+            .map_or(0, |span| span.range.start as u32);
+        return Err(Error { error: format!("Unknown variable '{}'", other), at, kind: ErrorKind::UnknownVariable });
+    }
+    let source = expression.to_string();
+    let var = var.to_string();
+    Ok(move |value: f32| {
+        let resolve = |name: &str| if name == var { Some(value) } else { None };
+        matches!(eval_expr(&expr, &Context::new(&source, &resolve)), Ok(result) if result > 0.0)
+    })
+}
+
+/// Parses the expression once and lazily evaluates it with `var` bound to
+/// each of `values` in turn. A parse error is returned right away, an
+/// evaluation error as the item for the value that caused it.
+pub fn evaluate_iter<'a>(expression: &str, var: &'a str, values: impl Iterator<Item = f32> + 'a)
+    -> Result<impl Iterator<Item = Result<f32, Error>> + 'a, Error> {
+    let compiled = Expression::compile(expression)?;
+    Ok(values.map(move |value| {
+        let resolve = |name: &str| if name == var { Some(value) } else { None };
+        eval_expr(&compiled.expr, &Context::new(&compiled.source, &resolve))
+    }))
+}
+
+/// Evaluates each line of the source as its own expression. An expression
+/// may continue on the next line while a parenthesis is open; blank lines are skipped.
+pub fn evaluate_lines(source: &str) -> Result<Vec<f32>, Error> {
+    let mut tokens = tokenizer::Tokens::new(source).with_newline_separators().peekable();
+    let mut values = vec![];
+    while tokens.peek().is_some() {
+        let mut line = tokens.by_ref().take_while(|token| !matches!(token, Ok(Token::Newline(..))));
+        let expr = parser::parse(&mut line)?;
+        values.push(eval_expr(&expr, &Context::new(source, &|_| None))?);
+    }
+    Ok(values)
+}
+
+/// Options affecting how a parsed expression is evaluated.
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    /// `&&` and `||` return one of their operands like Python's `and`/`or`:
+    /// `a && b` is `b` when `a` is truthy and `a` otherwise, `a || b` is `a`
+    /// when it is truthy and `b` otherwise. By default they return 1 or 0.
+    pub value_preserving_logic: bool,
+    /// Operations that are almost certainly mistakes, like rounding infinity,
+    /// are errors instead of quietly producing a non-finite result.
+    pub strict: bool,
+    /// How `round` resolves fractions. Division is always carried out in floating
+    /// point and is not affected.
+    pub rounding: RoundingMode,
+    /// `min()`, `max()`, `sum()` and `product()` without arguments return the
+    /// identity of the operation (+inf, -inf, 0 and 1) instead of an error.
+    pub empty_aggregate_identity: bool,
+    /// `sqrt`, `ln` and `asin` outside their domain are errors pointing at the
+    /// function instead of returning NaN.
+    pub domain_checks: bool,
+    /// Calls to functions that are not built in are errors instead of returning 0.
+    pub reject_unknown_functions: bool,
+    /// A result that is infinite or NaN is an error.
+    pub finite_results: bool,
+    /// Longest expression, in bytes, that is parsed at all.
+    pub max_length: Option<usize>,
+    /// Deepest nesting of operations, calls and parentheses.
+    pub max_depth: Option<u32>,
+    /// Most nodes evaluated, counting each repetition of a `sum` or `product` body.
+    pub max_steps: Option<u32>,
+    /// What a variable without a value evaluates to.
+    pub unknown_variables: UnknownVariables,
+}
+
+impl EvalOptions {
+    /// Safe settings for formulas from untrusted users: input size, nesting and
+    /// work are capped, unknown functions and non-finite values are errors, and
+    /// so are `sqrt`, `ln` and `asin` outside their domain. Unknown variables are
+    /// always errors.
+    pub fn sandboxed() -> EvalOptions {
+        EvalOptions {
+            strict: true,
+            domain_checks: true,
+            reject_unknown_functions: true,
+            finite_results: true,
+            max_length: Some(1000),
+            max_depth: Some(32),
+            max_steps: Some(10_000),
+            ..EvalOptions::default()
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+#[derive(Clone, Copy)]
+pub enum RoundingMode {
+    /// Halfway cases away from zero, so `round(2.5)` is 3.
+    #[default]
+    Nearest,
+    TowardZero,
+    Floor,
+    Ceil,
+}
+
+#[derive(Debug, PartialEq, Default)]
+#[derive(Clone, Copy)]
+pub enum UnknownVariables {
+    /// An "Unknown variable" error at the variable.
+    #[default]
+    Error,
+    /// The given value, reported as `DiagnosticKind::DefaultedVariable`.
+    DefaultTo(f32),
+}
+
+impl RoundingMode {
+    fn apply<F: Real>(self, value: F) -> F {
+        match self {
+            RoundingMode::Nearest => value.round(),
+            RoundingMode::TowardZero => value.trunc(),
+            RoundingMode::Floor => value.floor(),
+            RoundingMode::Ceil => value.ceil(),
+        }
+    }
+}
+
+pub fn evaluate_with_options(expression: &str, options: &EvalOptions) -> Result<f32, Error> {
+    evaluate_limited(expression, &|_| None, options)
+}
+
+/// Evaluates a formula from an untrusted source with `EvalOptions::sandboxed()`,
+/// taking variables from `vars`. No caller-defined functions are available.
+pub fn evaluate_sandboxed<K>(expression: &str, vars: &HashMap<K, f32>) -> Result<f32, Error>
+    where K: Borrow<str> + Hash + Eq {
+    evaluate_limited(expression, &|name| vars.get(name).copied(), &EvalOptions::sandboxed())
+}
+
+// applies the limits of the options that concern the whole expression
+fn evaluate_limited(expression: &str, resolve: &dyn Fn(&str) -> Option<f32>, options: &EvalOptions) -> Result<f32, Error> {
+    let parser = Parser { max_length: options.max_length, max_depth: options.max_depth, ..Parser::default() };
+    let expr = parser.parse(expression)?.expr;
+    let steps = Cell::new(options.max_steps.unwrap_or(0));
+    let mut ctx = Context::new(expression, resolve).with_options(options.clone());
+    if options.max_steps.is_some() {
+        ctx = ctx.with_step_budget(&steps);
+    }
+    let value = eval_expr(&expr, &ctx)?;
+    if options.finite_results && !value.is_finite() {
+        return Err(Error { error: format!("Result is not finite: {}", value), at: 0, kind: ErrorKind::Other });
+    }
+    Ok(value)
+}
+
+// operations and calls count one level each, parentheses none
+fn tree_depth(expr: &Expr) -> u32 {
+    match expr {
+        Expr::Number(_) | Expr::Variable(_) => 1,
+        Expr::Unary{ expr, .. } => 1 + tree_depth(expr),
+        Expr::Binary(bin) => 1 + tree_depth(&bin.left).max(tree_depth(&bin.right)),
+        Expr::Func(func) => 1 + func.params.iter().map(tree_depth).max().unwrap_or(0),
+        Expr::Index(element) => 1 + tree_depth(&element.index),
+        Expr::Ternary(ternary) => 1 + tree_depth(&ternary.condition).max(tree_depth(&ternary.then)).max(tree_depth(&ternary.otherwise))
+    }
+}
+
+pub fn evaluate_with_parse_options(expression: &str, options: &ParseOptions) -> Result<f32, Error> {
+    let mut tokens = tokenizer::Tokens::with_options(expression, options.clone());
+    let expr = parser::parse(&mut tokens)?;
+    eval_expr(&expr, &Context::new(expression, &|_| None))
+}
+
+/// Outcome of `evaluate_detailed`, separating valid computations that
+/// produced a non-finite number from expressions that failed to evaluate.
+#[derive(Debug)]
+pub enum EvalResult {
+    Ok(f32),
+    Nan,
+    Infinite(f32), // either f32::INFINITY or f32::NEG_INFINITY
+    Err(Error)
+}
+
+pub fn evaluate_detailed(expression: &str) -> EvalResult {
+    match evaluate(expression) {
+        Ok(value) if value.is_nan() => EvalResult::Nan,
+        Ok(value) if value.is_infinite() => EvalResult::Infinite(value),
+        Ok(value) => EvalResult::Ok(value),
+        Err(err) => EvalResult::Err(err)
+    }
+}
+
+/// Evaluates the expression and multiplies the result by `factor`.
+pub fn evaluate_scaled(expression: &str, factor: f32) -> Result<f32, Error> {
+    Ok(evaluate(expression)? * factor)
+}
+
+/// Evaluates the expression as an index into a collection of `len` items,
+/// rounding to the nearest integer.
+pub fn evaluate_index(expression: &str, len: usize) -> Result<usize, Error> {
+    let value = evaluate(expression)?.round();
+    if value >= 0.0 && (value as f64) < len as f64 {
+        Ok(value as usize)
+    } else {
+        Err(Error {
+            error: format!("Index {} is out of range 0..{}", value, len),
+            at: 0,
+            kind: ErrorKind::Other
+        })
+    }
+}
+
+/// Evaluates the expression resolving variables from `vars`. A variable that also
+/// has an entry in `scales` is multiplied by it when resolved, which lets the same
+/// formula run against inputs given in different units.
+pub fn evaluate_with_scaled_vars<K>(expression: &str, vars: &HashMap<K, f32>, scales: &HashMap<K, f32>) -> Result<f32, Error>
+    where K: Borrow<str> + Hash + Eq {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let resolve = |name: &str| vars.get(name).map(|value| value * scales.get(name).unwrap_or(&1.0));
+    eval_expr(&expr, &Context::new(expression, &resolve))
+}
+
+/// Evaluates a predicate such as `x > 5 && y < 2`. Expressions that do not result
+/// in a boolean, as told by `result_type`, are rejected before evaluation.
+pub fn evaluate_filter<K>(expression: &str, vars: &HashMap<K, f32>) -> Result<bool, Error>
+    where K: Borrow<str> + Hash + Eq {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    expect_boolean(&expr, expression)?;
+    let resolve = |name: &str| vars.get(name).copied();
+    Ok(eval_expr(&expr, &Context::new(expression, &resolve))? > 0.0)
+}
+
+// rejects an expression whose result is not a boolean, pointing at its outermost node
+fn expect_boolean(expr: &Expr, source: &str) -> Result<(), Error> {
+    if types::root_type(expr, source)? != ValueType::Boolean {
+        return Err(Error {
+            error: format!("'{}' is not a boolean expression", source.trim()),
+            at: expr_at(expr),
+            kind: ErrorKind::Other
+        });
+    }
+    Ok(())
+}
+
+/// Returns the names of the functions the expression calls, without
+/// duplicates and in the order they first appear in the source.
+pub fn used_functions(expression: &str) -> Result<Vec<String>, Error> {
     let mut tokens = tokenizer::Tokens::new(expression);
     let expr = parser::parse(&mut tokens)?;
-    Ok(eval_expr(&expr, expression))
+    let mut names = vec![];
+    collect_functions(&expr, expression, &mut names);
+    Ok(names)
+}
+
+/// Returns the names of the variables the expression reads, without
+/// duplicates and in the order they first appear in the source. The counter
+/// `i` of `sum(i, lo, hi, body)` is included, since it only counts when the
+/// variables given to the evaluator leave it without a value.
+pub fn free_variables(expression: &str) -> Result<Vec<String>, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let mut names = vec![];
+    collect_variables(&expr, expression, true, &mut names);
+    Ok(names)
+}
+
+/// Returns the nesting depth of the parsed expression, i.e. the height of its
+/// tree: a number or variable is 1, and each operator, prefix operator or
+/// function call adds one level above its deepest operand. Parentheses only
+/// group and add nothing, so `((1))` is as deep as `1`. Chains like `1 + 2 + 3`
+/// nest to the left and grow one level per operator.
+pub fn depth(expression: &str) -> Result<u32, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    Ok(tree_depth(&parser::parse(&mut tokens)?))
+}
+
+// `sum(i, lo, hi, body)` only counts with `i` when `i` has no value, so unless
+// the caller knows `i` gets none, the counter is listed as free as well
+fn collect_variables(expr: &Expr, source: &str, free_counters: bool, names: &mut Vec<String>) {
+    collect_variables_in(expr, source, free_counters, &mut vec![], names);
 }
 
-fn eval_expr(expr:&Expr, expression: &str) -> f32 {
+fn collect_variables_in<'s>(expr: &Expr, source: &'s str, free_counters: bool, bound: &mut Vec<&'s str>, names: &mut Vec<String>) {
     match expr {
-        Expr::Number(pos) => expression[pos.to_range()].parse::<f32>().unwrap(),
+        Expr::Number(_) => {}
+        Expr::Variable(pos) => {
+            let name = &source[pos.to_range()];
+            if !bound.contains(&name) && !names.iter().any(|known| known == name) {
+                names.push(name.to_string());
+            }
+        }
+        Expr::Unary{ expr, .. } => collect_variables_in(expr, source, free_counters, bound, names),
+        Expr::Index(element) => {
+            collect_variables_in(&Expr::Variable(element.base), source, free_counters, bound, names);
+            collect_variables_in(&element.index, source, free_counters, bound, names);
+        }
+        Expr::Ternary(ternary) => {
+            collect_variables_in(&ternary.condition, source, free_counters, bound, names);
+            collect_variables_in(&ternary.then, source, free_counters, bound, names);
+            collect_variables_in(&ternary.otherwise, source, free_counters, bound, names);
+        }
         Expr::Binary(bin) => {
-            let left = eval_expr(&bin.left, expression);
-            let right = eval_expr(&bin.right, expression);
-            let operator = operator::from(bin.operator_ix);
-            match operator.char1 {
-                '+' => left + right,
-                '-' => left - right,
-                '*' => left * right,
-                '/' => left / right,
-                '>' if operator.char2 == Some('=') => if left >= right {1.0} else {0.0},
-                '<' if operator.char2 == Some('=') => if left <= right {1.0} else {0.0},
-                '>' => if left > right {1.0} else {0.0},
-                '<' => if left < right {1.0} else {0.0},
-                '=' => if left == right {1.0} else {0.0},
-                _ => panic!("Unexpected operator") // this arm should be handled by the parser
-            }
-        }
-        Expr::Unary{ expr, operator_ix } => {
-            let operator = operator::from(*operator_ix);
-            match operator.char1 {
-                '+' => eval_expr(expr, expression),
-                '-' => -eval_expr(expr, expression),
-                _ => panic!("Unexpected operator") // this arm should be handled by the parser
-            }
-        }
-        Expr::Variable(_pos)=> {
-            1f32
+            collect_variables_in(&bin.left, source, free_counters, bound, names);
+            collect_variables_in(&bin.right, source, free_counters, bound, names);
         }
-        Expr::Func( boxed_func ) => {
-            let FuncExpr { name, params } = &**boxed_func;
-            match &expression[name.to_range()] {
-                "pi" => std::f64::consts::PI as f32,
-                "if" => {
-                    if params.len() != 3 {
-                        panic!("Expected 3 arguments into 'if' function");
-                    }
-                    if eval_expr(&params[0], expression) > 0.0 {
-                        eval_expr(&params[1], expression)
-                    } else {
-                        eval_expr(&params[2], expression)
-                    }
+        Expr::Func(func) => match range_variable(&source[func.name.to_range()], &func.params) {
+            Some(Expr::Variable(var)) => {
+                if free_counters {
+                    collect_variables_in(&func.params[0], source, free_counters, bound, names);
                 }
-                _ => 0f32
+                collect_variables_in(&func.params[1], source, free_counters, bound, names);
+                collect_variables_in(&func.params[2], source, free_counters, bound, names);
+                bound.push(&source[var.to_range()]);
+                collect_variables_in(&func.params[3], source, free_counters, bound, names);
+                bound.pop();
+            }
+            _ => for param in &func.params {
+                collect_variables_in(param, source, free_counters, bound, names);
+            }
+        }
+    }
+}
+
+fn collect_functions(expr: &Expr, source: &str, names: &mut Vec<String>) {
+    match expr {
+        Expr::Number(_) | Expr::Variable(_) => {}
+        Expr::Unary{ expr, .. } => collect_functions(expr, source, names),
+        Expr::Index(element) => collect_functions(&element.index, source, names),
+        Expr::Ternary(ternary) => {
+            collect_functions(&ternary.condition, source, names);
+            collect_functions(&ternary.then, source, names);
+            collect_functions(&ternary.otherwise, source, names);
+        }
+        Expr::Binary(bin) => {
+            collect_functions(&bin.left, source, names);
+            collect_functions(&bin.right, source, names);
+        }
+        Expr::Func(func) => {
+            let name = &source[func.name.to_range()];
+            if !names.iter().any(|known| known == name) {
+                names.push(name.to_string());
+            }
+            for param in &func.params {
+                collect_functions(param, source, names);
             }
         }
     }
 }
 
+// everything the evaluator needs besides the tree itself
+struct Context<'a, F = f32> {
+    source: &'a str,
+    resolve: &'a dyn Fn(&str) -> Option<F>,
+    options: EvalOptions,
+    functions: Option<&'a dyn Functions<F>>,
+    diagnostics: Option<&'a RefCell<Vec<Diagnostic>>>,
+    steps_left: Option<&'a Cell<u32>>,
+    elements: Option<Elements<'a, F>>,
+}
 
-#[derive(Debug)]
-pub struct Error {
-    error: String,
-    at: u32,
+// values of indexed variables by name and index
+type Elements<'a, F> = &'a dyn Fn(&str, usize) -> Option<F>;
+
+// functions defined by the caller, consulted before the built-in ones
+trait Functions<F = f32> {
+    fn contains(&self, name: &str) -> bool;
+    fn call(&self, name: &str, at: u32, args: &[F]) -> Result<F, Error>;
 }
 
-#[cfg(test)]
-mod evaluate_should {
-    use super::*;
-    
-    #[test]
-    fn have_16_bytes_token_max() {
-        assert!(std::mem::size_of::<Expr>() <= 16);
+impl<'a, F: Real> Context<'a, F> {
+    fn new(source: &'a str, resolve: &'a dyn Fn(&str) -> Option<F>) -> Context<'a, F> {
+        Context { source, resolve, options: EvalOptions::default(), functions: None, diagnostics: None, steps_left: None, elements: None }
     }
 
-    #[test]
-    fn handle_numbers() {
-        assert_eq!(evaluate("1").unwrap(), 1.0);
-        assert_eq!(evaluate("99.88").unwrap(), 99.88f32)
+    fn with_options(self, options: EvalOptions) -> Context<'a, F> {
+        Context { options, ..self }
     }
 
-    #[test]
-    fn handle_unary_expr() {
-        assert_eq!(evaluate("-1").unwrap(), -1f32);
-        assert_eq!(evaluate("--2").unwrap(), 2f32);
+    fn with_functions(self, functions: &'a dyn Functions<F>) -> Context<'a, F> {
+        Context { functions: Some(functions), ..self }
     }
 
-    #[test]
-    fn handle_pi_func() {
-        assert_eq!(evaluate("pi()").unwrap(), std::f64::consts::PI as f32);
+    fn with_diagnostics(self, diagnostics: &'a RefCell<Vec<Diagnostic>>) -> Context<'a, F> {
+        Context { diagnostics: Some(diagnostics), ..self }
     }
 
-    #[test]
-    fn handle_if_func() {
-        assert_eq!(evaluate("if(1 > 0, 10, -1)").unwrap(), 10.0);
-        assert_eq!(evaluate("if(1 < 0, 10, -1)").unwrap(), -1.0);
-        assert_eq!(evaluate("if(1 = 1, 10, -1)").unwrap(), 10.0);
-        assert_eq!(evaluate("if(1 >= 0, 10, -1)").unwrap(), 10.0);
-        assert_eq!(evaluate("if(1 >= 1, 10, -1)").unwrap(), 10.0);
-        assert_eq!(evaluate("if(1 <= 0, 10, -1)").unwrap(), -1.0);
-        assert_eq!(evaluate("if(1 <= 1, 10, -1)").unwrap(), 10.0);
+    fn with_step_budget(self, steps_left: &'a Cell<u32>) -> Context<'a, F> {
+        Context { steps_left: Some(steps_left), ..self }
     }
 
-    #[test]
-    fn handle_variable() {
-        assert_eq!(evaluate("abc").unwrap(), 1.0);
+    fn with_elements(self, elements: Elements<'a, F>) -> Context<'a, F> {
+        Context { elements: Some(elements), ..self }
+    }
+
+    fn step(&self, expr: &Expr) -> Result<(), Error> {
+        match self.steps_left {
+            Some(steps_left) if steps_left.get() == 0 => Err(Error {
+                error: format!("Evaluation takes more than {} steps", self.options.max_steps.unwrap_or(0)),
+                at: expr_at(expr),
+                kind: ErrorKind::Other
+            }),
+            Some(steps_left) => {
+                steps_left.set(steps_left.get() - 1);
+                Ok(())
+            }
+            None => Ok(())
+        }
+    }
+
+    fn report(&self, kind: DiagnosticKind, at: u32) {
+        if let Some(diagnostics) = self.diagnostics {
+            diagnostics.borrow_mut().push(Diagnostic { kind, at });
+        }
+    }
+
+    fn reported(&self) -> usize {
+        self.diagnostics.map_or(0, |diagnostics| diagnostics.borrow().len())
+    }
+
+    // the tokenizer only produces valid numbers, but a hand-built tree may point anywhere
+    fn number(&self, pos: Position) -> Result<F, Error> {
+        let text = &self.source[pos.to_range()];
+        let digits = without_separators(text);
+        let (number, scale) = match digits.strip_suffix('%') {
+            Some(percent) => (percent, F::HUNDRED),
+            None => (&*digits, F::ONE)
+        };
+        let value = match hex::parse_prefixed(number) {
+            Some(value) => Ok(F::from_f64(value)),
+            None => number.parse::<F>()
+        };
+        value.map(|value| value / scale).map_err(|_| Error {
+            error: format!("Invalid number '{}'", text),
+            at: pos.at,
+            kind: ErrorKind::InvalidNumber
+        })
+    }
+
+    fn variable(&self, pos: Position) -> Result<F, Error> {
+        let name = &self.source[pos.to_range()];
+        match ((self.resolve)(name), self.options.unknown_variables) {
+            (Some(value), _) => Ok(value),
+            (None, UnknownVariables::DefaultTo(value)) => {
+                self.report(DiagnosticKind::DefaultedVariable, pos.at);
+                Ok(F::from_f64(value as f64))
+            }
+            (None, UnknownVariables::Error) => Err(Error {
+                error: format!("Unknown variable '{}'", name),
+                at: pos.at,
+                kind: ErrorKind::UnknownVariable
+            })
+        }
+    }
+
+    fn element(&self, base: Position, index: F, index_at: u32) -> Result<F, Error> {
+        if index < F::ZERO || index.fract() != F::ZERO || !index.is_finite() {
+            return Err(Error {
+                error: format!("Index must be a non-negative integer but got {}", index),
+                at: index_at,
+                kind: ErrorKind::Other
+            });
+        }
+        let name = &self.source[base.to_range()];
+        let index = index.to_f64() as usize;
+        self.elements.and_then(|elements| elements(name, index)).ok_or_else(|| Error {
+            error: format!("Unknown variable '{}[{}]'", name, index),
+            at: base.at,
+            kind: ErrorKind::UnknownVariable
+        })
+    }
+}
+
+// the float parsers do not accept the underscores in `1_000`
+fn without_separators(text: &str) -> std::borrow::Cow<'_, str> {
+    if text.contains('_') { text.replace('_', "").into() } else { text.into() }
+}
+
+fn eval_expr<F: Real>(expr:&Expr, ctx: &Context<F>) -> Result<F, Error> {
+    ctx.step(expr)?;
+    match expr {
+        Expr::Number(pos) => ctx.number(*pos),
+        Expr::Binary(bin) => {
+            let left = eval_expr(&bin.left, ctx)?;
+            if let Some(value) = short_circuit(bin.operator_ix, bin.at, left, &ctx.options)? {
+                return Ok(value);
+            }
+            let right = eval_expr(&bin.right, ctx)?;
+            let value = eval_binary(bin.operator_ix, bin.at, left, right, &ctx.options)?;
+            if let Some(kind) = diagnostics::binary_hazard(bin.operator_ix, left, right, value) {
+                ctx.report(kind, bin.at);
+            }
+            Ok(value)
+        }
+        Expr::Unary{ expr, operator_ix, at } => {
+            eval_unary(*operator_ix, *at, eval_expr(expr, ctx)?)
+        }
+        Expr::Variable(pos)=> ctx.variable(*pos),
+        Expr::Index(element) => ctx.element(element.base, eval_expr(&element.index, ctx)?, expr_at(&element.index)),
+        // only the taken branch is evaluated
+        Expr::Ternary(ternary) => if eval_expr(&ternary.condition, ctx)? > F::ZERO {
+            eval_expr(&ternary.then, ctx)
+        } else {
+            eval_expr(&ternary.otherwise, ctx)
+        },
+        Expr::Func( boxed_func ) => {
+            let reported = ctx.reported();
+            let value = eval_call(boxed_func, ctx)?;
+            // only where the value became non-finite, not everywhere it passes through
+            if let Some(kind) = diagnostics::result_hazard(value).filter(|_| ctx.reported() == reported) {
+                ctx.report(kind, boxed_func.name.at);
+            }
+            Ok(value)
+        }
+    }
+}
+
+fn expr_at(expr: &Expr) -> u32 {
+    match expr {
+        Expr::Number(pos) | Expr::Variable(pos) => pos.at,
+        Expr::Index(element) => element.base.at,
+        Expr::Ternary(ternary) => ternary.at,
+        Expr::Unary{ at, .. } => *at,
+        Expr::Binary(bin) => bin.at,
+        Expr::Func(func) => func.name.at
+    }
+}
+
+fn eval_call<F: Real>(func: &FuncExpr, ctx: &Context<F>) -> Result<F, Error> {
+    let FuncExpr { name, params } = func;
+    let name_str = &ctx.source[name.to_range()];
+    if let Some(functions) = ctx.functions.filter(|functions| functions.contains(name_str)) {
+        let args = params.iter().map(|param| eval_expr(param, ctx)).collect::<Result<Vec<F>, Error>>()?;
+        return functions.call(name_str, name.at, &args);
+    }
+    if let Some(Expr::Variable(var)) = range_variable(name_str, params) {
+        // a variable with a value makes it a plain aggregate like `sum(a, b, c, d)`
+        let var = &ctx.source[var.to_range()];
+        if (ctx.resolve)(var).is_none() {
+            return eval_range(name_str, name.at, var, &params[1..], ctx, &|param, ctx| eval_expr(param, ctx));
+        }
+    }
+    eval_func(name_str, name.at, &ctx.options, params, &mut |param| eval_expr(param, ctx))
+}
+
+// the value of `&&` or `||` when the left operand alone decides it, so the right one is not evaluated
+fn short_circuit<F: Real>(operator_ix: u8, at: u32, left: F, options: &EvalOptions) -> Result<Option<F>, Error> {
+    let decided = match valid_operator(operator_ix, at)? {
+        operator if operator.word.is_some() => false,
+        operator if operator.char1 == '&' => left <= F::ZERO,
+        operator if operator.char1 == '|' => left > F::ZERO,
+        _ => false
+    };
+    if !decided {
+        return Ok(None);
+    }
+    Ok(Some(if options.value_preserving_logic { left } else { F::from_bool(left > F::ZERO) }))
+}
+
+fn eval_binary<F: Real>(operator_ix: u8, at: u32, left: F, right: F, options: &EvalOptions) -> Result<F, Error> {
+    let operator = valid_operator(operator_ix, at)?;
+    if !operator.infix() {
+        return Err(misplaced_operator(operator, at));
+    }
+    if operator.word == Some("xor") {
+        return Ok(F::from_bool((left > F::ZERO) != (right > F::ZERO)));
+    }
+    let value = match operator.char1 {
+        '&' if options.value_preserving_logic => if left > F::ZERO {right} else {left},
+        '|' if options.value_preserving_logic => if left > F::ZERO {left} else {right},
+        '&' => F::from_bool(left > F::ZERO && right > F::ZERO),
+        '|' => F::from_bool(left > F::ZERO || right > F::ZERO),
+        '+' => left + right,
+        '-' => left - right,
+        '*' => left * right,
+        '/' => left / right,
+        '%' => left % right,
+        '^' => left.powf(right),
+        '>' if operator.char2 == Some('=') => F::from_bool(left >= right),
+        '<' if operator.char2 == Some('=') => F::from_bool(left <= right),
+        '>' => F::from_bool(left > right),
+        '<' => F::from_bool(left < right),
+        '=' => F::from_bool(left == right),
+        '!' => F::from_bool(left != right),
+        _ => return Err(misplaced_operator(operator, at))
+    };
+    Ok(value)
+}
+
+fn eval_unary<F: Real>(operator_ix: u8, at: u32, value: F) -> Result<F, Error> {
+    let operator = valid_operator(operator_ix, at)?;
+    let value = match operator.char1 {
+        '+' => value,
+        '-' => -value,
+        '!' => F::from_bool(value <= F::ZERO), // the opposite of how 'if' and '&&' read it
+        _ => return Err(misplaced_operator(operator, at))
+    };
+    Ok(value)
+}
+
+// the parser never puts an operator where it does not belong, but a hand-built tree can
+fn misplaced_operator(operator: operator::Operator, at: u32) -> Error {
+    let error = if operator.prefix {
+        format!("Operator '{}' cannot be used between two operands", operator)
+    } else {
+        format!("Operator '{}' cannot be used as a prefix", operator)
+    };
+    Error { error, at, kind: ErrorKind::UnexpectedToken }
+}
+
+// a hand-built tree may carry an index the tokenizer would never produce
+fn valid_operator(operator_ix: u8, at: u32) -> Result<operator::Operator, Error> {
+    operator::try_from(operator_ix).ok_or_else(|| Error {
+        error: format!("Invalid operator index {}", operator_ix),
+        at,
+        kind: ErrorKind::Other
+    })
+}
+
+// the bound variable of `sum(i, lo, hi, body)`, `product(i, lo, hi, body)` or `avg(i, lo, hi, body)`;
+// the caller still has to check it is a variable without a value, otherwise it is a plain aggregate
+fn range_variable<'p, P>(name: &str, params: &'p [P]) -> Option<&'p P> {
+    if matches!(name, "sum" | "product" | "avg") && params.len() == 4 {
+        params.first()
+    } else {
+        None
+    }
+}
+
+// evaluates body with var bound to each integer from lo to hi, both included
+fn eval_range<P, F: Real>(name: &str, at: u32, var: &str, params: &[P], ctx: &Context<F>,
+                 eval: &impl Fn(&P, &Context<F>) -> Result<F, Error>) -> Result<F, Error> {
+    let lo = eval(&params[0], ctx)?;
+    let hi = eval(&params[1], ctx)?;
+    let error = |error: String| Err(Error { error, at, kind: ErrorKind::Other });
+    if lo.fract() != F::ZERO || hi.fract() != F::ZERO {
+        return error(format!("Range of '{}' must have integer bounds but got {} and {}", name, lo, hi));
+    }
+    if lo > hi {
+        return error("Invalid bounds: lo > hi".to_string());
+    }
+    // counting in F would get stuck where adding 1 no longer changes the value,
+    // so the counter is an integer and every value it takes must convert exactly
+    let (lo, hi) = (lo.to_f64() as i64, hi.to_f64() as i64);
+    let largest = lo.saturating_abs().max(hi.saturating_abs()).saturating_add(1) as f64;
+    if F::from_f64(largest).to_f64() != largest {
+        return error(format!("Range of '{}' is too large to count from {} to {}", name, lo, hi));
+    }
+    let current = Cell::new(F::ZERO);
+    let resolve = |name: &str| if name == var { Some(current.get()) } else { (ctx.resolve)(name) };
+    let inner = Context { source: ctx.source, resolve: &resolve, options: ctx.options.clone(), functions: ctx.functions, diagnostics: ctx.diagnostics, steps_left: ctx.steps_left, elements: ctx.elements };
+    let mut value = if name == "product" {F::ONE} else {F::ZERO};
+    for counter in lo..=hi {
+        current.set(F::from_f64(counter as f64));
+        let term = eval(&params[2], &inner)?;
+        value = if name == "product" { value * term } else { value + term };
+    }
+    if name == "avg" {
+        value = value / F::from_f64((hi - lo + 1) as f64);
+    }
+    Ok(value)
+}
+
+// params are evaluated on demand so that 'if' only evaluates the taken branch
+fn eval_func<P, F: Real>(name: &str, at: u32, options: &EvalOptions, params: &[P], eval: &mut impl FnMut(&P) -> Result<F, Error>) -> Result<F, Error> {
+    let value = match name {
+        "pi" => F::PI,
+        "e" => F::E,
+        "tau" => F::TAU,
+        "if" => {
+            expect_args(name, at, params.len(), 3)?;
+            if eval(&params[0])? > F::ZERO {
+                eval(&params[1])?
+            } else {
+                eval(&params[2])?
+            }
+        }
+        "approx" => {
+            expect_args(name, at, params.len(), 3)?;
+            let a = eval(&params[0])?;
+            let b = eval(&params[1])?;
+            let eps = eval(&params[2])?;
+            F::from_bool((a - b).abs() <= eps)
+        }
+        "dist" => {
+            expect_args(name, at, params.len(), 2)?;
+            (eval(&params[0])? - eval(&params[1])?).abs()
+        }
+        "sin_norm" | "cos_norm" | "tan_norm" => {
+            expect_args(name, at, params.len(), 1)?;
+            // reduce in f64 so that large accumulated angles keep their precision
+            let angle = eval(&params[0])?.to_f64() % std::f64::consts::TAU;
+            let value = match name {
+                "sin_norm" => angle.sin(),
+                "cos_norm" => angle.cos(),
+                _ => angle.tan()
+            };
+            F::from_f64(value)
+        }
+        "floor" | "ceil" | "round" => {
+            expect_args(name, at, params.len(), 1)?;
+            let value = eval(&params[0])?;
+            if options.strict && !value.is_finite() {
+                return Err(Error {
+                    error: format!("Cannot apply '{}' to non-finite value {}", name, value),
+                    at,
+                    kind: ErrorKind::Other
+                });
+            }
+            match name {
+                "floor" => value.floor(),
+                "ceil" => value.ceil(),
+                _ => options.rounding.apply(value)
+            }
+        }
+        "abs" | "sin" | "cos" | "tan" | "exp" => {
+            expect_args(name, at, params.len(), 1)?;
+            let value = eval(&params[0])?;
+            match name {
+                "abs" => value.abs(),
+                "sin" => value.sin(),
+                "cos" => value.cos(),
+                "tan" => value.tan(),
+                _ => value.exp()
+            }
+        }
+        "pow" => {
+            expect_args(name, at, params.len(), 2)?;
+            eval(&params[0])?.powf(eval(&params[1])?)
+        }
+        // `log(x)` is base 10, `log(x, base)` any other
+        "log" if params.len() == 2 => {
+            let value = eval(&params[0])?;
+            let base = eval(&params[1])?;
+            if options.domain_checks && (value <= F::ZERO || base <= F::ZERO || base == F::ONE) {
+                return Err(Error {
+                    error: format!("{} domain error", name),
+                    at,
+                    kind: ErrorKind::Other
+                });
+            }
+            value.log(base)
+        }
+        "sqrt" | "ln" | "log" | "asin" => {
+            expect_args(name, at, params.len(), 1)?;
+            let value = eval(&params[0])?;
+            let in_domain = match name {
+                "sqrt" => value >= F::ZERO,
+                "ln" | "log" => value > F::ZERO,
+                _ => (-F::ONE..=F::ONE).contains(&value)
+            };
+            if options.domain_checks && !in_domain {
+                return Err(Error {
+                    error: format!("{} domain error", name),
+                    at,
+                    kind: ErrorKind::Other
+                });
+            }
+            match name {
+                "sqrt" => value.sqrt(),
+                "ln" => value.ln(),
+                "log" => value.log10(),
+                _ => value.asin()
+            }
+        }
+        // same as the operators, including what dividing by zero does
+        "add" | "sub" | "mul" | "div" => {
+            expect_args(name, at, params.len(), 2)?;
+            let symbol = match name {
+                "add" => "+",
+                "sub" => "-",
+                "mul" => "*",
+                _ => "/"
+            };
+            let operator_ix = operator::find(symbol).unwrap();
+            eval_binary(operator_ix, at, eval(&params[0])?, eval(&params[1])?, options)?
+        }
+        // the fallback is only evaluated when it is used, like a branch of 'if'
+        "safe_div" => {
+            expect_args(name, at, params.len(), 3)?;
+            let a = eval(&params[0])?;
+            let b = eval(&params[1])?;
+            if b == F::ZERO { eval(&params[2])? } else { a / b }
+        }
+        "min" | "max" | "sum" | "product" => {
+            if params.is_empty() && !options.empty_aggregate_identity {
+                return Err(Error {
+                    error: format!("Expected at least 1 argument into '{}' function", name),
+                    at,
+                    kind: ErrorKind::ArityMismatch
+                });
+            }
+            let (identity, combine): (F, fn(F, F) -> F) = match name {
+                "min" => (F::INFINITY, F::min),
+                "max" => (F::NEG_INFINITY, F::max),
+                "sum" => (F::ZERO, |a, b| a + b),
+                _ => (F::ONE, |a, b| a * b)
+            };
+            let mut value = identity;
+            for param in params {
+                value = combine(value, eval(param)?);
+            }
+            value
+        }
+        "avg" => {
+            if params.is_empty() {
+                return Err(Error {
+                    error: format!("Expected at least 1 argument into '{}' function", name),
+                    at,
+                    kind: ErrorKind::ArityMismatch
+                });
+            }
+            let mut total = F::ZERO;
+            for param in params {
+                total = total + eval(param)?;
+            }
+            total / F::from_f64(params.len() as f64)
+        }
+        "clamp" | "clamp_side" | "between" | "wrap" => {
+            expect_args(name, at, params.len(), 3)?;
+            let x = eval(&params[0])?;
+            let lo = eval(&params[1])?;
+            let hi = eval(&params[2])?;
+            // bounds usually come from variables, so an inverted range is a mistake worth reporting
+            if lo > hi {
+                return Err(Error {
+                    error: "Invalid bounds: lo > hi".to_string(),
+                    at,
+                    kind: ErrorKind::Other
+                });
+            }
+            match name {
+                "clamp" => x.max(lo).min(hi),
+                // which side clamp would cut x off at, if any
+                "clamp_side" => if x < lo {-F::ONE} else if x > hi {F::ONE} else {F::ZERO},
+                "between" => F::from_bool(lo <= x && x <= hi),
+                _ if lo == hi => lo,
+                _ => lo + (x - lo).rem_euclid(hi - lo)
+            }
+        }
+        // a vector literal, see `evaluate_vector`
+        #[cfg(feature = "vectors")]
+        "[" => return Err(Error {
+            error: "Vectors are only supported by evaluate_vector".to_string(),
+            at,
+            kind: ErrorKind::Other
+        }),
+        _ if options.reject_unknown_functions => return Err(Error {
+            error: format!("Unknown function '{}'", name),
+            at,
+            kind: ErrorKind::UnknownFunction
+        }),
+        _ => F::ZERO
+    };
+    Ok(value)
+}
+
+fn expect_args(name: &str, at: u32, count: usize, expected: usize) -> Result<(), Error> {
+    if count == expected {
+        return Ok(());
+    }
+    Err(Error {
+        error: format!("Expected {} argument{} into '{}' function", expected, if expected == 1 {""} else {"s"}, name),
+        at,
+        kind: ErrorKind::ArityMismatch
+    })
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Error {
+    error: String,
+    at: u32,
+    kind: ErrorKind,
+}
+
+/// What went wrong, for callers that react to some errors differently.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub enum ErrorKind {
+    /// The input has no tokens at all, e.g. `""` or only whitespace.
+    EmptyInput,
+    /// The input ended where a value was expected, e.g. after a trailing operator.
+    UnexpectedEof,
+    /// A token that cannot appear where it is, e.g. a reserved character or `1 2`.
+    UnexpectedToken,
+    /// A parenthesis or bracket without its counterpart.
+    UnbalancedParens,
+    InvalidNumber,
+    UnknownVariable,
+    UnknownFunction,
+    /// A function called with the wrong number of arguments.
+    ArityMismatch,
+    Other,
+}
+
+impl Error {
+    /// An error for functions passed to `evaluate_with_funcs` to return; it is
+    /// reported at the call that failed.
+    pub fn new(message: impl Into<String>) -> Error {
+        Error { error: message.into(), at: 0, kind: ErrorKind::Other }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.error
+    }
+
+    /// Byte offset in the expression the error points at.
+    pub fn at(&self) -> u32 {
+        self.at
+    }
+
+    /// Character offset in `source`, the expression, the error points at.
+    pub fn char_position(&self, source: &str) -> u32 {
+        char_offset(source, self.at)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} (at {})", self.error, self.at)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod evaluate_should {
+    use super::*;
+    
+    #[test]
+    fn have_16_bytes_token_max() {
+        assert!(std::mem::size_of::<Expr>() <= 16);
+    }
+
+    #[test]
+    fn error_on_invalid_operator_index() {
+        let expr = Expr::Binary(Box::new(BinaryExpr {
+            left: Expr::Number(Position { at: 0, len: 1 }),
+            right: Expr::Number(Position { at: 4, len: 1 }),
+            operator_ix: 200,
+            at: 2
+        }));
+        let result = eval_expr::<f32>(&expr, &Context::new("1 ? 2", &|_| None));
+        assert!(matches!(result, Err(e) if e.at == 2 && e.error.contains("200")));
+
+        let expr = Expr::Unary { expr: Box::new(Expr::Number(Position { at: 1, len: 1 })), operator_ix: 99, at: 0 };
+        assert_matches!(eval_expr::<f32>(&expr, &Context::new("?1", &|_| None)), Err(..));
+    }
+
+    #[test]
+    fn error_on_misplaced_operator() {
+        let one = || Expr::Number(Position { at: 1, len: 1 });
+        let expr = Expr::Unary { expr: Box::new(one()), operator_ix: operator::find("/").unwrap(), at: 0 };
+        let error = eval(&expr, "/1").unwrap_err();
+        assert_eq!((error.error.as_str(), error.at, error.kind), ("Operator '/' cannot be used as a prefix", 0, ErrorKind::UnexpectedToken));
+
+        let expr = Expr::Binary(Box::new(BinaryExpr { left: one(), right: one(), operator_ix: operator::find("!").unwrap(), at: 0 }));
+        let error = eval(&expr, "!1").unwrap_err();
+        assert_eq!((error.error.as_str(), error.kind), ("Operator '!' cannot be used between two operands", ErrorKind::UnexpectedToken));
+    }
+
+    #[test]
+    fn handle_numbers() {
+        assert_eq!(evaluate("1").unwrap(), 1.0);
+        assert_eq!(evaluate("99.88").unwrap(), 99.88f32)
+    }
+
+    #[test]
+    fn handle_unary_expr() {
+        assert_eq!(evaluate("-1").unwrap(), -1f32);
+        assert_eq!(evaluate("--2").unwrap(), 2f32);
+    }
+
+    #[test]
+    fn error_on_non_prefix_operator() {
+        assert!(matches!(evaluate("*2"), Err(e) if e.error.contains("'*' cannot be used as a prefix")));
+        assert!(matches!(evaluate("/3"), Err(e) if e.error.contains("'/' cannot be used as a prefix")));
+        assert_eq!(evaluate("+2").unwrap(), 2f32);
+        assert_eq!(evaluate("-2").unwrap(), -2f32);
+    }
+
+    #[test]
+    fn handle_pi_func() {
+        assert_eq!(evaluate("pi()").unwrap(), std::f64::consts::PI as f32);
+    }
+
+    #[test]
+    fn handle_if_func() {
+        assert_eq!(evaluate("if(1 > 0, 10, -1)").unwrap(), 10.0);
+        assert_eq!(evaluate("if(1 < 0, 10, -1)").unwrap(), -1.0);
+        assert_eq!(evaluate("if(1 = 1, 10, -1)").unwrap(), 10.0);
+        assert_eq!(evaluate("if(1 >= 0, 10, -1)").unwrap(), 10.0);
+        assert_eq!(evaluate("if(1 >= 1, 10, -1)").unwrap(), 10.0);
+        assert_eq!(evaluate("if(1 <= 0, 10, -1)").unwrap(), -1.0);
+        assert_eq!(evaluate("if(1 <= 1, 10, -1)").unwrap(), 10.0);
+    }
+
+    #[test]
+    fn resolve_variables_only_in_taken_if_branch() {
+        let looked_up = RefCell::new(vec![]);
+        let resolve = |name: &str| {
+            looked_up.borrow_mut().push(name.to_string());
+            if name == "known" { Some(2.0) } else { None }
+        };
+        let source = "if(1 > 0, known, undefined_var) + if(1 < 0, undefined_var, known)";
+        let expr = parse(source).unwrap();
+        assert_eq!(eval_expr::<f32>(expr.expr(), &Context::new(source, &resolve)).unwrap(), 4.0);
+        assert_eq!(*looked_up.borrow(), vec!["known", "known"]);
+        let vars: HashMap<&str, f32> = [("known", 2.0)].iter().cloned().collect();
+        assert_eq!(evaluate_with("if(1 > 0, known, undefined_var)", &vars).unwrap(), 2.0);
+        assert_matches!(evaluate_with("if(1 < 0, known, undefined_var)", &vars), Err(..));
+    }
+
+    #[test]
+    fn handle_ternary_operator() {
+        assert_eq!(evaluate("1 > 0 ? 10 : -1").unwrap(), 10.0);
+        assert_eq!(evaluate("1 < 0 ? 10 : -1").unwrap(), -1.0);
+        assert_eq!(evaluate("1 = 1 ? 10 : -1").unwrap(), 10.0);
+        assert_eq!(evaluate("1 >= 0 ? 10 : -1").unwrap(), 10.0);
+        assert_eq!(evaluate("1 >= 1 ? 10 : -1").unwrap(), 10.0);
+        assert_eq!(evaluate("1 <= 0 ? 10 : -1").unwrap(), -1.0);
+        assert_eq!(evaluate("1 <= 1 ? 10 : -1").unwrap(), 10.0);
+        assert_eq!(evaluate("0 ? 1 : 0 ? 2 : 3").unwrap(), 3.0);
+        assert_eq!(evaluate("1 ? 0 ? 1 : 2 : 3").unwrap(), 2.0);
+        assert_eq!(evaluate("2 * (1 > 0 ? 3 : 4) + 1").unwrap(), 7.0);
+        assert_eq!(evaluate("max(1 ? 5 : 6, 2)").unwrap(), 5.0);
+        assert_eq!(evaluate("1 + -1 ? 5 : 6").unwrap(), 6.0);
+        assert_eq!(evaluate("-1 ? 2 : 3").unwrap(), 3.0);
+        assert_eq!(evaluate("-1 ? 2 : 3").unwrap(), evaluate("if(-1, 2, 3)").unwrap());
+        assert_eq!(evaluate("!0 ? 2 : 3").unwrap(), 2.0);
+        assert_eq!(evaluate("1 > 0 ? 1 : undefined_var").unwrap(), 1.0);
+        let error = evaluate("1 ? 2").unwrap_err();
+        assert_eq!(error.error, "Expected ':' after '?' but reached the end");
+        assert_eq!(error.at, 2);
+        assert_matches!(evaluate("1 ? 2 , 3"), Err(..));
+        assert_matches!(evaluate("1 : 2"), Err(..));
+    }
+
+    #[test]
+    fn handle_safe_div_func() {
+        assert_eq!(evaluate("safe_div(1, 0, -1)").unwrap(), -1.0);
+        assert_eq!(evaluate("safe_div(6, 2, -1)").unwrap(), 3.0);
+        assert_eq!(evaluate("safe_div(6, 2, unknown)").unwrap(), 3.0);
+        assert_eq!(evaluate("safe_div(0, -0, 7)").unwrap(), 7.0);
+        assert_matches!(evaluate("safe_div(1, 0)"), Err(..));
+    }
+
+    #[test]
+    fn handle_approx_func() {
+        assert_eq!(evaluate("approx(0.1 + 0.2, 0.3, 0.000001)").unwrap(), 1.0);
+        assert_eq!(evaluate("approx(1, 1.001, 0.0001)").unwrap(), 0.0);
+        assert_eq!(evaluate("if(approx(2, 2.5, 1), 10, -1)").unwrap(), 10.0);
+    }
+
+    #[test]
+    fn handle_dist_func() {
+        assert_eq!(evaluate("dist(3, 7)").unwrap(), 4.0);
+        assert_eq!(evaluate("dist(7, 3)").unwrap(), 4.0);
+    }
+
+    #[test]
+    fn handle_normalized_trig_funcs() {
+        let reduced = 10_000_000f64 % std::f64::consts::TAU;
+        assert!((evaluate("sin_norm(10000000)").unwrap() - reduced.sin() as f32).abs() < 1e-6);
+        assert!((evaluate("cos_norm(10000000)").unwrap() - reduced.cos() as f32).abs() < 1e-6);
+        assert!((evaluate("tan_norm(10000000)").unwrap() - reduced.tan() as f32).abs() < 1e-5);
+        assert_eq!(evaluate("sin_norm(0)").unwrap(), 0.0);
+    }
+
+    #[test]
+    fn filter_with_compiled_predicate() {
+        let mut values = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        let above_3 = compile_predicate("x > 3", "x").unwrap();
+        values.retain(|x| above_3(*x));
+        assert_eq!(values, vec![4.0, 5.0]);
+        let even = compile_predicate("n % 2 = 0 && n != 4", "n").unwrap();
+        assert_eq!((1..=6).map(|n| n as f32).filter(|n| even(*n)).collect::<Vec<_>>(), vec![2.0, 6.0]);
+        assert_eq!(compile_predicate("x + 1", "x").err().unwrap().error, "'x + 1' is not a boolean expression");
+        let error = compile_predicate("x > xy + y", "x").err().unwrap();
+        assert_eq!(error.error, "Unknown variable 'xy'");
+        assert_eq!(error.at, 4);
+    }
+
+    #[test]
+    fn evaluate_lazily_over_values() {
+        let values = vec![1.0, 2.0, 0.0, 4.0];
+        let expected: Vec<f32> = values.iter()
+            .map(|&x| evaluate_with("10 / x + 1", &[("x", x)].iter().copied().collect::<HashMap<_, _>>()).unwrap())
+            .collect();
+        let results = evaluate_iter("10 / x + 1", "x", values.into_iter()).unwrap();
+        assert_eq!(results.collect::<Result<Vec<f32>, Error>>().unwrap(), expected);
+        let mut results = evaluate_iter("x + y", "x", (0..).map(|x| x as f32)).unwrap();
+        assert_eq!(results.next().unwrap().unwrap_err().error, "Unknown variable 'y'");
+        assert!(evaluate_iter("x +", "x", std::iter::empty()).is_err());
+    }
+
+    #[test]
+    fn handle_variable() {
+        let vars: HashMap<&str, f32> = [("abc", 2.0), ("x", 0.5)].iter().cloned().collect();
+        assert_eq!(evaluate_with("abc", &vars).unwrap(), 2.0);
+        assert_eq!(evaluate_with("abc * (x + 1)", &vars).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn measure_depth() {
+        assert_eq!(depth("1").unwrap(), 1);
+        assert_eq!(depth("((1))").unwrap(), 1);
+        assert_eq!(depth("1+2").unwrap(), 2);
+        assert_eq!(depth("1 + 2 + 3").unwrap(), 3);
+        assert_eq!(depth("1 + (2 + 3)").unwrap(), 3);
+        assert_eq!(depth("-max(1, 2 * 3)").unwrap(), 4);
+        assert_eq!(depth("pi()").unwrap(), 1);
+        assert_matches!(depth("1 +"), Err(..));
+    }
+
+    #[test]
+    fn list_free_variables() {
+        assert_eq!(free_variables("a * (b + a) - max(c, 1)").unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(free_variables("sum(i, lo, n, i * x) + i").unwrap(), vec!["i", "lo", "n", "x"]);
+        assert_eq!(free_variables("sum(a, b, c, d)").unwrap(), vec!["a", "b", "c", "d"]);
+        assert!(free_variables("pi() + 1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn handle_indexed_variables() {
+        let v = [1.5, 2.0, 4.0];
+        let resolve = |name: &str, index: Option<usize>| match (name, index) {
+            ("v", Some(index)) => v.get(index).copied(),
+            ("n", None) => Some(2.0),
+            _ => None
+        };
+        assert_eq!(evaluate_with_resolver("v[0] + v[1]", &resolve).unwrap(), 3.5);
+        assert_eq!(evaluate_with_resolver("v[n] * v[ n - 1 ]", &resolve).unwrap(), 8.0);
+        let error = evaluate_with_resolver("v[3]", &resolve).unwrap_err();
+        assert_eq!(error.error, "Unknown variable 'v[3]'");
+        assert_eq!(error.at, 0);
+        let error = evaluate_with_resolver("1 + v[n / 4]", &resolve).unwrap_err();
+        assert_eq!(error.error, "Index must be a non-negative integer but got 0.5");
+        assert_eq!(error.at, 8);
+        assert_matches!(evaluate_with_resolver("v[0 - 1]", &resolve), Err(..));
+        assert_matches!(evaluate_with_resolver("n[0]", &resolve), Err(..));
+        assert_matches!(evaluate_with_resolver("v[0", &resolve), Err(..));
+        assert_matches!(evaluate("v[0]"), Err(..));
+    }
+
+    #[test]
+    fn separate_parsing_from_evaluation() {
+        let source = "max(1, 2) * 3";
+        let parsed = parse(source).unwrap();
+        assert_eq!(parsed.eval().unwrap(), evaluate(source).unwrap());
+        assert_eq!(parsed.clone().eval().unwrap(), 6.0);
+        assert_eq!(parsed.source(), source);
+        let expr = parsed.into_expr();
+        assert_eq!(eval(&expr, source).unwrap(), 6.0);
+        match &expr {
+            Expr::Binary(bin) => {
+                assert_eq!(operator_symbol(bin.operator_ix).unwrap(), "*");
+                assert_matches!(&bin.left, Expr::Func(func) if &source[func.name.to_range()] == "max" && func.params.len() == 2);
+            }
+            _ => panic!()
+        }
+        assert_eq!(operator_symbol(200), None);
+        assert_matches!(parse("1 +"), Err(..));
+    }
+
+    #[test]
+    fn reuse_configured_parser() {
+        let parser = Parser::new().max_depth(3).max_length(12)
+            .options(ParseOptions { require_operator_spacing: true, ..ParseOptions::default() });
+        assert_eq!(parser.parse("1 + 2 * 3").unwrap().eval().unwrap(), 7.0);
+        assert_eq!(parser.parse("max(1, 2)").unwrap().eval().unwrap(), 2.0);
+        assert_eq!(parser.parse("1 + 2 * 3 ^ 2").unwrap_err().error, "Expression is longer than 12 bytes");
+        assert_eq!(parser.parse("1 + -(2 * 3)").unwrap_err().error, "Expression is nested deeper than 3");
+        assert_eq!(parser.parse("(((1)))").unwrap_err().at, 3);
+        assert!(parser.parse("1-2").is_err());
+        assert!(Parser::default().parse("1-2").is_ok());
+    }
+
+    #[test]
+    fn stop_parsing_at_max_depth() {
+        let nested = "(".repeat(100000) + "1" + &")".repeat(100000);
+        let error = Parser::new().max_depth(10).parse(&nested).unwrap_err();
+        assert_eq!((error.error.as_str(), error.at), ("Expression is nested deeper than 10", 10));
+        let options = EvalOptions { max_depth: Some(10), ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options(&nested, &options).unwrap_err().at, 10);
+        let chained = "1 + ".repeat(100000) + "1";
+        assert_eq!(Parser::new().max_depth(10).parse(&chained).unwrap_err().at, 38);
+        assert_eq!(Parser::new().max_depth(3).parse("1 + 2 * 3").unwrap().eval().unwrap(), 7.0);
+    }
+
+    #[test]
+    fn reuse_compiled_expression() {
+        let expression = Expression::compile(&String::from("x * x + 1")).unwrap();
+        let mut vars = HashMap::new();
+        for x in 0..3 {
+            vars.insert("x", x as f32);
+            assert_eq!(expression.eval_with(&vars).unwrap(), (x * x + 1) as f32);
+        }
+        assert_eq!(expression.eval().unwrap_err().at, 0);
+        assert_eq!(Expression::compile("2 ^ 3").unwrap().eval().unwrap(), 8.0);
+        assert!(Expression::compile("2 ^").is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    fn reload_serialized_expression() {
+        let json = serde_json::to_string(&Expression::compile("max(x, 2) * 3 + (x > 1 ? 1 : 0)").unwrap()).unwrap();
+        let expression: Expression = serde_json::from_str(&json).unwrap();
+        let vars: HashMap<&str, f32> = [("x", 3.0)].iter().copied().collect();
+        assert_eq!(expression.eval_with(&vars).unwrap(), 10.0);
+        let tree: Expr = serde_json::from_str(&serde_json::to_string(expression.expr()).unwrap()).unwrap();
+        assert_eq!(eval(&tree, expression.source()).unwrap_err().error, "Unknown variable 'x'");
+    }
+
+    #[test]
+    fn reuse_compiled_closure() {
+        let closure = compile_to_closure("a * 2 + b").unwrap();
+        let mut vars: HashMap<String, f32> = HashMap::new();
+        vars.insert("a".to_string(), 3.0);
+        vars.insert("b".to_string(), 1.0);
+        assert_eq!(closure(&vars).unwrap(), 7.0);
+        vars.insert("a".to_string(), -1.0);
+        assert_eq!(closure(&vars).unwrap(), -1.0);
+        vars.remove("b");
+        assert_eq!(closure(&vars).unwrap_err().at, 8);
+        assert!(compile_to_closure("a *").is_err());
+    }
+
+    #[test]
+    fn error_on_unbound_variable() {
+        let vars: HashMap<&str, f32> = [("x", 1.0)].iter().cloned().collect();
+        let error = evaluate_with("x + y", &vars).unwrap_err();
+        assert_eq!(error.error, "Unknown variable 'y'");
+        assert_eq!(error.at, 4);
+        assert_eq!(evaluate("2 * abc").unwrap_err().at, 4);
+    }
+
+    #[test]
+    fn handle_scaled_result() {
+        assert_eq!(evaluate_scaled("1 + 2", 10.0).unwrap(), 30f32);
+    }
+
+    #[test]
+    fn handle_scaled_variables() {
+        let vars: HashMap<&str, f32> = [("km", 1.5), ("m", 20.0)].iter().cloned().collect();
+        let scales: HashMap<&str, f32> = [("km", 1000.0)].iter().cloned().collect();
+        assert_eq!(evaluate_with_scaled_vars("km + m", &vars, &scales).unwrap(), 1520f32);
+        assert_matches!(evaluate_with_scaled_vars("km + cm", &vars, &scales), Err(..));
+    }
+
+    #[test]
+    fn handle_operator_spacing() {
+        let options = ParseOptions { require_operator_spacing: true, ..ParseOptions::default() };
+        assert_matches!(evaluate_with_parse_options("1+2", &options), Err(..));
+        assert_matches!(evaluate_with_parse_options("1 +2", &options), Err(..));
+        assert_eq!(evaluate_with_parse_options("1 + 2", &options).unwrap(), 3f32);
+        assert_eq!(evaluate_with_parse_options("1 - -2", &options).unwrap(), 3f32);
+        assert_eq!(evaluate("1+2").unwrap(), 3f32);
+    }
+
+    #[test]
+    fn list_used_functions() {
+        assert_eq!(used_functions("if(sin(x) > 0, max(1,2), 3)").unwrap(), vec!["if", "sin", "max"]);
+        assert_eq!(used_functions("pi() * pi() + x").unwrap(), vec!["pi"]);
+        assert!(used_functions("1 + 2").unwrap().is_empty());
+    }
+
+    #[test]
+    fn distinguish_non_finite_results() {
+        assert_matches!(evaluate_detailed("1/0"), EvalResult::Infinite(v) if v > 0.0);
+        assert_matches!(evaluate_detailed("-1/0"), EvalResult::Infinite(v) if v < 0.0);
+        assert_matches!(evaluate_detailed("0/0"), EvalResult::Nan);
+        assert_matches!(evaluate_detailed("1+1"), EvalResult::Ok(v) if v == 2.0);
+        assert_matches!(evaluate_detailed("1+"), EvalResult::Err(..));
+    }
+
+    #[test]
+    fn handle_xor() {
+        assert_eq!(evaluate("(1 > 0) xor (2 > 1)").unwrap(), 0.0);
+        assert_eq!(evaluate("(1 > 0) xor (0 > 1)").unwrap(), 1.0);
+        assert_eq!(evaluate("1 = 1 xor 2 < 1").unwrap(), 1.0);
+        assert_matches!(evaluate("xor + 1"), Err(..));
+    }
+
+    #[test]
+    fn short_circuit_logical_operators() {
+        assert_eq!(evaluate("1 || undefined_var").unwrap(), 1.0);
+        assert_eq!(evaluate("0 && undefined_var").unwrap(), 0.0);
+        assert_eq!(evaluate("1 > 0 || 1 / sqrt(-1)").unwrap(), 1.0);
+        assert_matches!(evaluate("0 || undefined_var"), Err(..));
+        assert_matches!(evaluate("1 && undefined_var"), Err(..));
+        let vars: HashMap<&str, f32> = [("a", 2.0), ("b", 5.0)].iter().cloned().collect();
+        assert_eq!(evaluate_with("a > 0 && b < 10", &vars).unwrap(), 1.0);
+        assert_eq!(evaluate_with("a = 1 || b = 2", &vars).unwrap(), 0.0);
+        let options = EvalOptions { value_preserving_logic: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("3 || nothing", &options).unwrap(), 3.0);
+        assert_eq!(evaluate_with_options("(-2) && nothing", &options).unwrap(), -2.0);
+    }
+
+    #[test]
+    fn handle_not_operator() {
+        let vars: HashMap<&str, f32> = [("x", 5.0)].iter().cloned().collect();
+        assert_eq!(evaluate_with("!(x > 3)", &vars).unwrap(), 0.0);
+        assert_eq!(evaluate_with("!(x > 7)", &vars).unwrap(), 1.0);
+        assert_eq!(evaluate("!0").unwrap(), 1.0);
+        assert_eq!(evaluate("!2").unwrap(), 0.0);
+        assert_eq!(evaluate("!!2").unwrap(), 1.0);
+        assert_eq!(evaluate("!0 && 0").unwrap(), 0.0);
+        assert_eq!(evaluate("!1 || 1").unwrap(), 1.0);
+        assert_eq!(evaluate_with("!x > 3", &vars).unwrap(), 0.0);
+        assert_eq!(evaluate("!2 ^ 0").unwrap(), 0.0);
+        assert_eq!(evaluate("if(!0, 10, 20)").unwrap(), 10.0);
+        assert_eq!(evaluate("1 != 2").unwrap(), 1.0);
+        assert_matches!(evaluate("1 ! 2"), Err(..));
+        assert_matches!(evaluate("!"), Err(..));
+    }
+
+    #[test]
+    fn handle_equality_operators() {
+        assert_eq!(evaluate("2 == 2").unwrap(), 1.0);
+        assert_eq!(evaluate("2 != 2").unwrap(), 0.0);
+        assert_eq!(evaluate("1 + 1 != 3 && 2 * 2 == 4").unwrap(), 1.0);
+        assert_eq!(evaluate("2 = 2").unwrap(), evaluate("2 == 2").unwrap());
+        match parse("1 != 0").unwrap().expr() {
+            Expr::Binary(bin) => assert_eq!(operator_symbol(bin.operator_ix).unwrap(), "!="),
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn handle_operator_aliases() {
+        let parse = |expression| parser::parse(&mut tokenizer::Tokens::new(expression)).unwrap();
+        match (parse("1 == 1"), parse("1 = 1")) {
+            (Expr::Binary(alias), Expr::Binary(canonical)) => assert_eq!(alias.operator_ix, canonical.operator_ix),
+            _ => panic!()
+        }
+        match (parse("1 => 1"), parse("1 >= 1")) {
+            (Expr::Binary(alias), Expr::Binary(canonical)) => assert_eq!(alias.operator_ix, canonical.operator_ix),
+            _ => panic!()
+        }
+        assert_eq!(evaluate("2 == 2").unwrap(), 1.0);
+        assert_eq!(evaluate("2 => 3").unwrap(), 0.0);
+        assert_eq!(evaluate("6 ÷ 2 × 3").unwrap(), 9.0);
+
+        let options = ParseOptions { aliases: OperatorAliases::new(), ..ParseOptions::default() };
+        assert_matches!(evaluate_with_parse_options("2 => 2", &options), Err(..));
+        assert_eq!(evaluate_with_parse_options("2 == 2", &options).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn handle_custom_operator_table() {
+        let mut aliases = OperatorAliases::replacing();
+        for (spelling, canonical) in &[("más", "+"), ("menos", "-"), ("×", "*"), ("÷", "/"), ("mayor", ">"), ("y", "&&")] {
+            assert!(aliases.add(spelling, canonical));
+        }
+        assert!(!aliases.add("elevado", "**"));
+        let options = ParseOptions { aliases, ..ParseOptions::default() };
+        assert_eq!(evaluate_with_parse_options("2 más 3 × 4", &options).unwrap(), 14.0);
+        assert_eq!(evaluate_with_parse_options("menos 8 ÷ 2", &options).unwrap(), -4.0);
+        assert_eq!(evaluate_with_parse_options("3 mayor 2 y 1 mayor 0", &options).unwrap(), 1.0);
+        assert_matches!(evaluate_with_parse_options("2 + 3", &options), Err(..));
+        assert_matches!(evaluate_with_parse_options("1 xor 0", &options), Err(..));
+    }
+
+    #[test]
+    fn handle_bounded_functions() {
+        assert_eq!(evaluate("clamp(5, 0, 3)").unwrap(), 3.0);
+        assert_eq!(evaluate("clamp(-1, 0, 3)").unwrap(), 0.0);
+        assert_eq!(evaluate("between(2, 0, 3)").unwrap(), 1.0);
+        assert_eq!(evaluate("between(4, 0, 3)").unwrap(), 0.0);
+        assert_eq!(evaluate("wrap(370, 0, 360)").unwrap(), 10.0);
+        assert_eq!(evaluate("wrap(-10, 0, 360)").unwrap(), 350.0);
+        assert_eq!(evaluate("clamp_side(-1, 0, 3)").unwrap(), -1.0);
+        assert_eq!(evaluate("clamp_side(3, 0, 3)").unwrap(), 0.0);
+        assert_eq!(evaluate("clamp_side(5, 0, 3)").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn error_on_inverted_bounds() {
+        let mut vars = HashMap::new();
+        vars.insert("lo", 10.0);
+        vars.insert("hi", 5.0);
+        let scales = HashMap::new();
+        for &(expression, at) in &[("clamp(7, lo, hi)", 0), ("between(7, lo, hi)", 0), ("1 + wrap(7, lo, hi)", 4), ("clamp_side(7, lo, hi)", 0)] {
+            let error = evaluate_with_scaled_vars(expression, &vars, &scales).unwrap_err();
+            assert_eq!(error.error, "Invalid bounds: lo > hi");
+            assert_eq!(error.at, at);
+        }
+        assert_eq!(evaluate_with_scaled_vars("clamp(7, hi, lo)", &vars, &scales).unwrap(), 7.0);
+    }
+
+    #[test]
+    fn handle_logic_operators() {
+        assert_eq!(evaluate("1 < 2 && 3 < 4").unwrap(), 1.0);
+        assert_eq!(evaluate("1 > 2 || 3 > 4").unwrap(), 0.0);
+        assert_eq!(evaluate("5 && 3").unwrap(), 1.0);
+        assert_eq!(evaluate("0 || 0 && 1 || 2").unwrap(), 1.0);
+        assert_matches!(evaluate("1 & 2"), Err(..));
+    }
+
+    #[test]
+    fn preserve_values_in_logic_operators() {
+        let options = EvalOptions { value_preserving_logic: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("5 && 3", &options).unwrap(), 3.0);
+        assert_eq!(evaluate_with_options("0 || 7", &options).unwrap(), 7.0);
+        assert_eq!(evaluate_with_options("(-2) && 3", &options).unwrap(), -2.0);
+        assert_eq!(evaluate_with_options("4 || 7", &options).unwrap(), 4.0);
+        assert_eq!(evaluate_with_options("5 && 3", &EvalOptions::default()).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn handle_rounding_functions() {
+        assert_eq!(evaluate("floor(2.7) + ceil(2.2) + round(2.5)").unwrap(), 8.0);
+        assert_eq!(evaluate("floor(-2.5)").unwrap(), -3.0);
+        assert_eq!(evaluate("floor(1/0)").unwrap(), f32::INFINITY);
+    }
+
+    #[test]
+    fn round_with_configured_mode() {
+        let expected = [(RoundingMode::Nearest, 3.0, -3.0), (RoundingMode::TowardZero, 2.0, -2.0),
+                        (RoundingMode::Floor, 2.0, -3.0), (RoundingMode::Ceil, 3.0, -2.0)];
+        for &(rounding, positive, negative) in &expected {
+            let options = EvalOptions { rounding, ..EvalOptions::default() };
+            assert_eq!(evaluate_with_options("round(2.5)", &options).unwrap(), positive);
+            assert_eq!(evaluate_with_options("round(0 - 2.5)", &options).unwrap(), negative);
+            assert_eq!(evaluate_with_options("7 / 2", &options).unwrap(), 3.5);
+        }
+    }
+
+    #[test]
+    fn error_on_rounding_non_finite_in_strict_mode() {
+        let strict = EvalOptions { strict: true, ..EvalOptions::default() };
+        let error = evaluate_with_options("1 + floor(1/0)", &strict).unwrap_err();
+        assert_eq!(error.error, "Cannot apply 'floor' to non-finite value inf");
+        assert_eq!(error.at, 4);
+        assert_matches!(evaluate_with_options("ceil(0/0)", &strict), Err(..));
+        assert_eq!(evaluate_with_options("round(1.4)", &strict).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn call_caller_defined_functions() {
+        type Func = Box<dyn Fn(&[f32]) -> Result<f32, Error>>;
+        let mut funcs: HashMap<&str, Func> = HashMap::new();
+        funcs.insert("double", Box::new(|args| Ok(args.iter().sum::<f32>() * 2.0)));
+        funcs.insert("max", Box::new(|_| Ok(-1.0)));
+        funcs.insert("if", Box::new(|_| Ok(-1.0)));
+        funcs.insert("fail", Box::new(|args| Err(Error::new(format!("Cannot fail with {}", args[0])))));
+        assert_eq!(evaluate_with_funcs("double(1, 2) + 1", &funcs).unwrap(), 7.0);
+        assert_eq!(evaluate_with_funcs("max(1, 2)", &funcs).unwrap(), -1.0);
+        assert_eq!(evaluate_with_funcs("if(1, 2, fail(3))", &funcs).unwrap(), 2.0);
+        let error = evaluate_with_funcs("1 + fail(3)", &funcs).unwrap_err();
+        assert_eq!(error.error, "Cannot fail with 3");
+        assert_eq!(error.at, 4);
+        let error = evaluate_with_funcs("2 * sqr(4)", &funcs).unwrap_err();
+        assert_eq!(error.error, "Unknown function 'sqr'");
+        assert_eq!(error.at, 4);
+    }
+
+    #[test]
+    fn evaluate_normal_formula_sandboxed() {
+        let vars: HashMap<&str, f32> = [("price", 20.0), ("rate", 0.25)].iter().cloned().collect();
+        assert_eq!(evaluate_sandboxed("round(price * (1 + rate)) + sum(i, 1, 3, i)", &vars).unwrap(), 31.0);
+    }
+
+    #[test]
+    fn reject_malicious_input_when_sandboxed() {
+        let vars: HashMap<&str, f32> = HashMap::new();
+        let nested = format!("{}1{}", "(1 + ".repeat(40), ")".repeat(40));
+        assert_eq!(evaluate_sandboxed(&nested, &vars).unwrap_err().error, "Expression is nested deeper than 32");
+        let huge = "1 + ".repeat(1000) + "1";
+        assert_eq!(evaluate_sandboxed(&huge, &vars).unwrap_err().error, "Expression is longer than 1000 bytes");
+        let endless = "sum(i, 1, 1000000, i)";
+        assert_eq!(evaluate_sandboxed(endless, &vars).unwrap_err().error, "Evaluation takes more than 10000 steps");
+        assert_eq!(evaluate_sandboxed("system(1)", &vars).unwrap_err().error, "Unknown function 'system'");
+        assert_eq!(evaluate_sandboxed("1 / 0", &vars).unwrap_err().error, "Result is not finite: inf");
+        assert_eq!(evaluate_sandboxed("secret", &vars).unwrap_err().error, "Unknown variable 'secret'");
+        assert_matches!(evaluate_sandboxed("sqrt(-1)", &vars), Err(..));
+    }
+
+    #[test]
+    fn error_on_out_of_domain_calls_with_domain_checks() {
+        let checked = EvalOptions { domain_checks: true, ..EvalOptions::default() };
+        let error = evaluate_with_options("1 + sqrt(-1)", &checked).unwrap_err();
+        assert_eq!(error.error, "sqrt domain error");
+        assert_eq!(error.at, 4);
+        assert_eq!(evaluate_with_options("ln(0 - 1)", &checked).unwrap_err().error, "ln domain error");
+        assert_eq!(evaluate_with_options("ln(0)", &checked).unwrap_err().error, "ln domain error");
+        assert_eq!(evaluate_with_options("asin(2)", &checked).unwrap_err().error, "asin domain error");
+        assert!(evaluate("sqrt(0 - 1)").unwrap().is_nan());
+    }
+
+    #[test]
+    fn handle_in_domain_calls_with_domain_checks() {
+        let checked = EvalOptions { domain_checks: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("sqrt(9)", &checked).unwrap(), 3.0);
+        assert_eq!(evaluate_with_options("sqrt(0)", &checked).unwrap(), 0.0);
+        assert_eq!(evaluate_with_options("ln(1)", &checked).unwrap(), 0.0);
+        assert_eq!(evaluate_with_options("asin(1)", &checked).unwrap(), std::f32::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    fn evaluate_each_line() {
+        assert_eq!(evaluate_lines("1 + 2\n\n  max(3,\n  4) * 2\n").unwrap(), vec![3.0, 8.0]);
+        assert!(evaluate_lines("").unwrap().is_empty());
+        assert_eq!(evaluate_lines("1\n2 +\n3").unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn handle_modulo() {
+        assert_eq!(evaluate("7 % 3").unwrap(), 1.0);
+        assert_eq!(evaluate("10 % 4 % 3").unwrap(), 2.0);
+        assert_eq!(evaluate("1 + 7 % 4 * 2").unwrap(), 7.0);
+        assert_eq!(evaluate("-7 % 3").unwrap(), -1.0);
+        assert!(evaluate("1 % 0").unwrap().is_nan());
+    }
+
+    #[test]
+    fn error_on_malformed_number() {
+        let error = evaluate("1.2.3").unwrap_err();
+        assert_eq!(error.error, "Malformed number '1.2.3'");
+        assert_eq!(error.at, 0);
+        assert_matches!(evaluate("1 + 2..5"), Err(..));
+        let expr = Expr::Number(Position { at: 0, len: 3 });
+        assert_eq!(eval_expr::<f32>(&expr, &Context::new("1+2", &|_| None)).unwrap_err().error, "Invalid number '1+2'");
+    }
+
+    #[test]
+    fn handle_power() {
+        assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), 512.0);
+        assert_eq!(evaluate("(2 ^ 3) ^ 2").unwrap(), 64.0);
+        assert_eq!(evaluate("2 * 3 ^ 2").unwrap(), 18.0);
+        assert_eq!(evaluate("2 ^ 3 * 2").unwrap(), 16.0);
+        assert_eq!(evaluate("9 ^ 0.5 - 1").unwrap(), 2.0);
+    }
+
+    #[test]
+    fn handle_math_functions() {
+        assert_eq!(evaluate("sqrt(9)").unwrap(), 3.0);
+        assert_eq!(evaluate("max(1,2,3)").unwrap(), 3.0);
+        assert_eq!(evaluate("min(4)").unwrap(), 4.0);
+        assert_eq!(evaluate("abs(-2) + sin(0) + cos(0) + tan(0)").unwrap(), 3.0);
+        assert_eq!(evaluate("log(1000) + log(8, 2) + ln(exp(2))").unwrap(), 8.0);
+        assert_eq!(evaluate("pow(2, 10)").unwrap(), 1024.0);
+    }
+
+    #[test]
+    fn error_on_wrong_number_of_arguments() {
+        let error = evaluate("1 + if(1, 2)").unwrap_err();
+        assert_eq!(error.error, "Expected 3 arguments into 'if' function");
+        assert_eq!(error.at, 4);
+        assert_eq!(evaluate("dist(1)").unwrap_err().error, "Expected 2 arguments into 'dist' function");
+        assert_eq!(evaluate("clamp(1, 2)").unwrap_err().error, "Expected 3 arguments into 'clamp' function");
+        assert_eq!(evaluate("floor()").unwrap_err().error, "Expected 1 argument into 'floor' function");
+    }
+
+    #[test]
+    fn error_on_math_function_arity() {
+        let error = evaluate("1 + sin()").unwrap_err();
+        assert_eq!(error.error, "Expected 1 argument into 'sin' function");
+        assert_eq!(error.at, 4);
+        assert_eq!(evaluate("pow(2)").unwrap_err().error, "Expected 2 arguments into 'pow' function");
+        assert_eq!(evaluate("log(1, 2, 3)").unwrap_err().error, "Expected 1 argument into 'log' function");
+    }
+
+    #[test]
+    fn handle_digit_separators() {
+        assert_eq!(evaluate("1_000_000").unwrap(), 1_000_000.0);
+        assert_eq!(evaluate("1_000.5 + 0.000_5").unwrap(), 1_000.5 + 0.000_5);
+        assert_eq!(evaluate("12_5%").unwrap(), 1.25);
+        assert_eq!(evaluate_interval("1_000").unwrap(), (1000.0, 1000.0));
+        assert_matches!(evaluate("1_"), Err(..));
+    }
+
+    #[test]
+    fn handle_hex_and_binary_literals() {
+        assert_eq!(evaluate("0xff + 1").unwrap(), 256.0);
+        assert_eq!(evaluate("0xFF == 255").unwrap(), 1.0);
+        assert_eq!(evaluate("0b1010 * 0B1").unwrap(), 10.0);
+        assert_eq!(evaluate("0").unwrap(), 0.0);
+        let error = evaluate("1 + 0xGG").unwrap_err();
+        assert_eq!(error.error, "Malformed number '0xGG'");
+        assert_eq!(error.at, 4);
+        assert_matches!(evaluate("0b12"), Err(..));
+    }
+
+    #[test]
+    fn handle_scientific_notation() {
+        assert_eq!(evaluate("1e3").unwrap(), 1000.0);
+        assert_eq!(evaluate("2.5E-1 + 1_0e+1").unwrap(), 100.25);
+        assert_eq!(evaluate("1e3%").unwrap(), 10.0);
+        assert_eq!(evaluate("1e").unwrap_err().at, 1);
+        assert_eq!(evaluate("1ex").unwrap_err().at, 1);
+    }
+
+    #[test]
+    fn handle_hex_float_literals() {
+        assert_eq!(evaluate("0x1p4").unwrap(), 16.0);
+        assert_eq!(evaluate("0x1.8p1").unwrap(), 3.0);
+        assert_eq!(evaluate("0x1p-2 + 0x1P+1").unwrap(), 2.25);
+        assert_eq!(evaluate_f64("0x1.999999999999ap-4").unwrap(), 0.1);
+        let error = evaluate("1 + 0x1.8").unwrap_err();
+        assert_eq!(error.error, "Malformed number '0x1.8'");
+        assert_eq!(error.at, 4);
+    }
+
+    #[test]
+    fn handle_percent_literals() {
+        assert_eq!(evaluate("50%").unwrap(), 0.5);
+        assert_eq!(evaluate("10 % 3").unwrap(), 1.0);
+        assert_eq!(evaluate("10% + 5").unwrap(), 5.1);
+        assert_eq!(evaluate("200 * 25%").unwrap(), 50.0);
+        // touching both numbers, `%` is the remainder
+        assert_eq!(evaluate("10%3").unwrap(), 1.0);
+        assert_eq!(evaluate("(10%) * 2").unwrap(), 0.2);
+    }
+
+    #[test]
+    fn handle_constants() {
+        assert_eq!(evaluate("pi()").unwrap(), std::f32::consts::PI);
+        assert_eq!(evaluate("e()").unwrap(), std::f32::consts::E);
+        assert_eq!(evaluate("tau()").unwrap(), std::f32::consts::TAU);
+    }
+
+    #[test]
+    fn display_error_with_position() {
+        let error = evaluate("2 * 1.2.3").unwrap_err();
+        assert_eq!(error.to_string(), "Malformed number '1.2.3' (at 4)");
+        assert_eq!(error.message(), "Malformed number '1.2.3'");
+        assert_eq!(error.at(), 4);
+        let boxed: Box<dyn std::error::Error> = Box::new(error);
+        assert_eq!(boxed.to_string(), "Malformed number '1.2.3' (at 4)");
+    }
+
+    #[test]
+    fn hint_at_trailing_operator() {
+        let error = evaluate("1 + ").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(error.error, "Expected a value after '+' but reached the end");
+        assert_eq!(error.at, 2);
+        let error = evaluate("2 * (1 - 3) * ").unwrap_err();
+        assert_eq!(error.error, "Expected a value after '*' but reached the end");
+        assert_eq!(error.at, 12);
+        assert_eq!(evaluate("-").unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(evaluate("1 $").unwrap_err().kind(), ErrorKind::UnexpectedToken);
+    }
+
+    #[test]
+    fn classify_errors() {
+        let kind = |expression| evaluate(expression).unwrap_err().kind();
+        assert_eq!(kind("1 2"), ErrorKind::UnexpectedToken);
+        assert_eq!(kind("1 */ 2"), ErrorKind::UnexpectedToken);
+        assert_eq!(kind("(1 + 2"), ErrorKind::UnbalancedParens);
+        assert_eq!(kind("1 + 2)"), ErrorKind::UnbalancedParens);
+        assert_eq!(kind("1.2.3"), ErrorKind::InvalidNumber);
+        assert_eq!(kind("x + 1"), ErrorKind::UnknownVariable);
+        assert_eq!(kind("floor(1, 2)"), ErrorKind::ArityMismatch);
+        let options = EvalOptions { reject_unknown_functions: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("nope(1)", &options).unwrap_err().kind(), ErrorKind::UnknownFunction);
+    }
+
+    #[test]
+    fn count_positions_in_characters() {
+        let source = "café × 2 + ";
+        let error = evaluate(source).unwrap_err();
+        assert_eq!((error.at(), error.char_position(source)), (11, 9));
+        assert_eq!(evaluate("1 + @").unwrap_err().char_position("1 + @"), 4);
+        let error = evaluate("é + @").unwrap_err();
+        assert_eq!((error.at(), error.char_position("é + @")), (5, 4));
+        assert_eq!(evaluate("é + .").unwrap_err().char_position("é + ."), 4);
+        let source = "größe + 1";
+        let spans = tokenize_with_spans(source).unwrap();
+        assert_eq!((spans[0].1.to_range(), spans[0].1.char_range(source)), (0..7, 0..5));
+        assert_eq!((spans[1].1.to_range(), spans[1].1.char_range(source)), (8..9, 6..7));
+    }
+
+    #[test]
+    fn tell_empty_input_from_truncated_input() {
+        assert_eq!(evaluate("").unwrap_err().kind(), ErrorKind::EmptyInput);
+        assert_eq!(evaluate(" \t ").unwrap_err().kind(), ErrorKind::EmptyInput);
+        assert_eq!(evaluate("(").unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn evaluate_filters_to_bool() {
+        let mut vars = HashMap::new();
+        vars.insert("x", 7.0);
+        assert!(evaluate_filter("x > 5", &vars).unwrap());
+        assert!(!evaluate_filter("(x <= 5 || x > 8)", &vars).unwrap());
+        assert!(evaluate_filter("x > 5 xor x > 8", &vars).unwrap());
+        assert!(!evaluate_filter("!(x > 3)", &vars).unwrap());
+        assert!(evaluate_filter("between(x, 5, 8)", &vars).unwrap());
+    }
+
+    #[test]
+    fn error_on_non_boolean_filter() {
+        let mut vars = HashMap::new();
+        vars.insert("x", 7.0);
+        let error = evaluate_filter("x + 1", &vars).unwrap_err();
+        assert_eq!(error.error, "'x + 1' is not a boolean expression");
+        assert_eq!(error.at, 2);
+        assert_eq!(evaluate_filter("3 * max(x, 1)", &vars).unwrap_err().at, 2);
+        assert_matches!(evaluate_filter("x", &vars), Err(..));
+        assert_matches!(evaluate_filter("if(x > 1, 1, 0)", &vars), Err(..));
+        assert_matches!(evaluate_filter("y > 1", &vars), Err(..));
+    }
+
+    #[test]
+    fn handle_operator_functions() {
+        assert_eq!(evaluate("mul(3, 4)").unwrap(), 12.0);
+        assert_eq!(evaluate("sub(add(1, 2), div(6, 3))").unwrap(), 1.0);
+        assert_eq!(evaluate("div(1, 0)").unwrap(), evaluate("1 / 0").unwrap());
+        assert_matches!(evaluate("add(1)"), Err(..));
+        assert_matches!(evaluate("mul(1, 2, 3)"), Err(..));
+    }
+
+    #[test]
+    fn handle_aggregates() {
+        assert_eq!(evaluate("min(3, 1, 2) + max(3, 1, 2)").unwrap(), 4.0);
+        assert_eq!(evaluate("sum(1, 2, 3) * product(2, 3)").unwrap(), 36.0);
+        assert_eq!(evaluate("max(-1)").unwrap(), -1.0);
+    }
+
+    #[test]
+    fn handle_aggregates_over_ranges() {
+        assert_eq!(evaluate("sum(i, 1, 5, i)").unwrap(), 15.0);
+        assert_eq!(evaluate("product(i, 1, 4, i)").unwrap(), 24.0);
+        assert_eq!(evaluate("sum(k, 0, 3, k * k) + sum(k, 2, 2, 1)").unwrap(), 15.0);
+        assert_eq!(evaluate("sum(1, 2, 3, 4)").unwrap(), 10.0);
+        assert_eq!(evaluate("avg(i, 1, 5, i)").unwrap(), 3.0);
+        assert_eq!(evaluate("avg(1, 2, 3, 6) + avg(4)").unwrap(), 7.0);
+    }
+
+    #[test]
+    fn handle_aggregates_of_four_variables() {
+        let vars: HashMap<&str, f32> = [("a", 1.0), ("b", 2.0), ("c", 3.0), ("d", 4.0)].iter().copied().collect();
+        assert_eq!(evaluate_with("sum(a, b, c, d)", &vars).unwrap(), 10.0);
+        assert_eq!(evaluate_with("product(a, b, c, d)", &vars).unwrap(), 24.0);
+        assert_eq!(evaluate_with("avg(a, b, c, d)", &vars).unwrap(), 2.5);
+        assert_eq!(evaluate_with("sum(i, a, d, i * b)", &vars).unwrap(), 20.0);
+    }
+
+    #[test]
+    fn error_on_invalid_range() {
+        let error = evaluate("1 + sum(i, 5, 1, i)").unwrap_err();
+        assert_eq!(error.error, "Invalid bounds: lo > hi");
+        assert_eq!(error.at, 4);
+        assert_matches!(evaluate("product(i, 1, 2.5, i)"), Err(..));
+        assert_matches!(evaluate("sum(i, 1/0, 1/0, i)"), Err(..));
+        let error = evaluate("sum(i, 16777216, 16777217, i)").unwrap_err();
+        assert_eq!(error.error, "Range of 'sum' is too large to count from 16777216 to 16777216");
+        assert_eq!(evaluate("sum(i, 16777213, 16777215, 1)").unwrap(), 3.0);
+        assert_matches!(evaluate("avg()"), Err(..));
+    }
+
+    #[test]
+    fn handle_empty_aggregates() {
+        for name in &["min", "max", "sum", "product"] {
+            let error = evaluate(&format!("1 + {}()", name)).unwrap_err();
+            assert_eq!(error.at, 4);
+        }
+        let options = EvalOptions { empty_aggregate_identity: true, ..EvalOptions::default() };
+        assert_eq!(evaluate_with_options("min()", &options).unwrap(), f32::INFINITY);
+        assert_eq!(evaluate_with_options("max()", &options).unwrap(), f32::NEG_INFINITY);
+        assert_eq!(evaluate_with_options("sum()", &options).unwrap(), 0.0);
+        assert_eq!(evaluate_with_options("product()", &options).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn evaluate_in_range_index() {
+        assert_eq!(evaluate_index("2.6", 5).unwrap(), 3);
+        assert_eq!(evaluate_index("-0.4", 5).unwrap(), 0);
+        assert_eq!(evaluate_index("2 * 2", 5).unwrap(), 4);
+    }
+
+    #[test]
+    fn error_on_out_of_range_index() {
+        assert_eq!(evaluate_index("10", 5).unwrap_err().error, "Index 10 is out of range 0..5");
+        assert_matches!(evaluate_index("-1", 5), Err(..));
+        assert_matches!(evaluate_index("0/0", 5), Err(..));
+        assert_matches!(evaluate_index("0", 0), Err(..));
+    }
+
+    #[test]
+    fn error_on_bare_function_name_with_strict_parens() {
+        let options = ParseOptions { strict_parens: true, ..ParseOptions::default() };
+        let error = evaluate_with_parse_options("1 + floor", &options).unwrap_err();
+        assert_eq!(error.error, "Did you forget '()' after 'floor'?");
+        assert_eq!(error.at, 4);
+        assert_matches!(evaluate_with_parse_options("2 * pi", &options), Err(..));
+        assert_eq!(evaluate_with_parse_options("floor (1.5) + pi() * 0", &options).unwrap(), 1.0);
+        assert_eq!(evaluate("floor").unwrap_err().error, "Unknown variable 'floor'");
+    }
+
+    #[test]
+    fn error_on_two_operators_in_a_row() {
+        let error = evaluate("1 */ 2").unwrap_err();
+        assert_eq!(error.error, "Two operators in a row: '*' then '/'");
+        assert_eq!(error.at, 3);
+        let error = evaluate("1 +* 2").unwrap_err();
+        assert_eq!(error.error, "Two operators in a row: '+' then '*'");
+        assert_eq!(error.at, 3);
+        assert_eq!(evaluate("1 * -2").unwrap(), -2.0);
     }
 
     #[test]
@@ -0,0 +1,65 @@
+use super::{Error, Context, Position, tokenizer, parser, fold, NodeFolder};
+
+/// Every numeric literal in the expression with its value and position, in
+/// source order, e.g. to look for magic numbers. Constants like `pi()` are
+/// function calls, not literals.
+pub fn literals(expression: &str) -> Result<Vec<(f32, Position)>, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    fold(&expr, expression, &Literals { ctx: Context::new(expression, &|_| None) })
+}
+
+struct Literals<'a> {
+    ctx: Context<'a>
+}
+
+impl NodeFolder<Vec<(f32, Position)>> for Literals<'_> {
+    fn number(&self, _text: &str, pos: Position) -> Result<Vec<(f32, Position)>, Error> {
+        Ok(vec![(self.ctx.number(pos)?, pos)])
+    }
+
+    fn variable(&self, _name: &str, _pos: Position) -> Result<Vec<(f32, Position)>, Error> {
+        Ok(vec![])
+    }
+
+    fn index(&self, _base: &str, _pos: Position, index: Vec<(f32, Position)>) -> Result<Vec<(f32, Position)>, Error> {
+        Ok(index)
+    }
+
+    fn func(&self, _name: &str, _pos: Position, params: Vec<Vec<(f32, Position)>>) -> Result<Vec<(f32, Position)>, Error> {
+        Ok(params.concat())
+    }
+
+    fn unary(&self, _operator: &str, _at: u32, value: Vec<(f32, Position)>) -> Result<Vec<(f32, Position)>, Error> {
+        Ok(value)
+    }
+
+    fn binary(&self, mut left: Vec<(f32, Position)>, _operator: &str, _at: u32, right: Vec<(f32, Position)>) -> Result<Vec<(f32, Position)>, Error> {
+        left.extend(right);
+        Ok(left)
+    }
+}
+
+
+#[cfg(test)]
+mod literals_should {
+    use super::*;
+
+    #[test]
+    fn list_literals_in_source_order() {
+        assert_eq!(literals("2 * pi() + 3.5").unwrap(), vec![
+            (2.0, Position { at: 0, len: 1 }),
+            (3.5, Position { at: 11, len: 3 })
+        ]);
+        assert_eq!(literals("max(x, 50%, -1)").unwrap(), vec![
+            (0.5, Position { at: 7, len: 3 }),
+            (1.0, Position { at: 13, len: 1 })
+        ]);
+        assert!(literals("x + y").unwrap().is_empty());
+    }
+
+    #[test]
+    fn error_on_invalid_expression() {
+        assert_matches!(literals("2 *"), Err(..));
+    }
+}
@@ -0,0 +1,100 @@
+use super::{Error, Expr, Context, tokenizer, parser, eval_expr, collect_variables};
+use std::collections::HashMap;
+
+/// Evaluates expressions like `evaluate_with`, remembering each result
+/// together with the values of the variables the expression reads. Calling
+/// again with the same expression and the same values for those variables
+/// returns the remembered result; other variables in the map do not matter.
+#[derive(Debug, Default)]
+pub struct MemoEvaluator {
+    entries: HashMap<String, Entry>
+}
+
+#[derive(Debug)]
+struct Entry {
+    expr: Expr,
+    free: Vec<String>,
+    // keyed on the bits of each free variable's value, None if it has no value
+    results: HashMap<Vec<Option<u32>>, f32>
+}
+
+impl MemoEvaluator {
+
+    pub fn new() -> MemoEvaluator {
+        MemoEvaluator::default()
+    }
+
+    pub fn evaluate(&mut self, expression: &str, vars: &HashMap<String, f32>) -> Result<f32, Error> {
+        if !self.entries.contains_key(expression) {
+            let expr = parser::parse(&mut tokenizer::Tokens::new(expression))?;
+            let mut free = vec![];
+            collect_variables(&expr, expression, true, &mut free);
+            let entry = Entry { expr, free, results: HashMap::new() };
+            self.entries.insert(expression.to_string(), entry);
+        }
+        let entry = self.entries.get_mut(expression).unwrap();
+        let key = entry.free.iter().map(|name| vars.get(name).map(|value| value.to_bits())).collect::<Vec<_>>();
+        if let Some(value) = entry.results.get(&key) {
+            return Ok(*value);
+        }
+        let resolve = |name: &str| vars.get(name).copied();
+        let value = eval_expr(&entry.expr, &Context::new(expression, &resolve))?;
+        entry.results.insert(key, value);
+        Ok(value)
+    }
+
+    /// Forgets every remembered result.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+
+#[cfg(test)]
+mod memo_should {
+    use super::*;
+
+    fn vars(pairs: &[(&str, f32)]) -> HashMap<String, f32> {
+        pairs.iter().map(|(name, value)| (name.to_string(), *value)).collect()
+    }
+
+    fn computed(memo: &MemoEvaluator, expression: &str) -> usize {
+        memo.entries[expression].results.len()
+    }
+
+    #[test]
+    fn reuse_result_for_same_inputs() {
+        let mut memo = MemoEvaluator::new();
+        assert_eq!(memo.evaluate("a * 2 + b", &vars(&[("a", 1.0), ("b", 3.0)])).unwrap(), 5.0);
+        assert_eq!(memo.evaluate("a * 2 + b", &vars(&[("a", 1.0), ("b", 3.0)])).unwrap(), 5.0);
+        // c is not read by the expression
+        assert_eq!(memo.evaluate("a * 2 + b", &vars(&[("a", 1.0), ("b", 3.0), ("c", 9.0)])).unwrap(), 5.0);
+        assert_eq!(computed(&memo, "a * 2 + b"), 1);
+    }
+
+    #[test]
+    fn recompute_when_input_changes() {
+        let mut memo = MemoEvaluator::new();
+        assert_eq!(memo.evaluate("a * 2 + b", &vars(&[("a", 1.0), ("b", 3.0)])).unwrap(), 5.0);
+        assert_eq!(memo.evaluate("a * 2 + b", &vars(&[("a", 2.0), ("b", 3.0)])).unwrap(), 7.0);
+        assert_eq!(computed(&memo, "a * 2 + b"), 2);
+        memo.clear();
+        assert_eq!(memo.evaluate("a * 2 + b", &vars(&[("a", 2.0), ("b", 3.0)])).unwrap(), 7.0);
+    }
+
+    #[test]
+    fn recompute_when_range_counter_gets_a_value() {
+        let mut memo = MemoEvaluator::new();
+        assert_eq!(memo.evaluate("sum(a, 1, 2, a)", &vars(&[])).unwrap(), 3.0);
+        assert_eq!(memo.evaluate("sum(a, 1, 2, a)", &vars(&[("a", 1.0)])).unwrap(), 5.0);
+        assert_eq!(memo.evaluate("sum(a, 1, 2, a)", &vars(&[("a", 10.0)])).unwrap(), 23.0);
+    }
+
+    #[test]
+    fn error_without_caching() {
+        let mut memo = MemoEvaluator::new();
+        assert_matches!(memo.evaluate("a + b", &vars(&[("a", 1.0)])), Err(..));
+        assert_eq!(memo.evaluate("a + b", &vars(&[("a", 1.0), ("b", 1.0)])).unwrap(), 2.0);
+        assert_matches!(memo.evaluate("a +", &vars(&[])), Err(..));
+    }
+}
@@ -0,0 +1,111 @@
+use super::{Error, ErrorKind, Expr, FuncExpr, operator, without_separators};
+use std::cmp::Ordering;
+
+/// A number type the generic evaluator can compute with. Operations return
+/// `Err` with a message when they are undefined for their operands and the
+/// evaluator attaches the position of the offending operator or function.
+pub(crate) trait Numeric: Clone {
+    fn literal(text: &str) -> Result<Self, String>;
+    fn variable(name: &str) -> Option<Self>;
+    fn from_bool(value: bool) -> Self;
+    fn truthy(&self) -> Result<bool, String>;
+    fn neg(self) -> Self;
+    fn add(self, rhs: Self) -> Result<Self, String>;
+    fn sub(self, rhs: Self) -> Result<Self, String>;
+    fn mul(self, rhs: Self) -> Result<Self, String>;
+    fn div(self, rhs: Self) -> Result<Self, String>;
+    // None when the values are unordered, which makes every comparison false
+    fn compare(&self, rhs: &Self) -> Result<Option<Ordering>, String>;
+    // for types that can be told apart but not ordered
+    fn equals(&self, rhs: &Self) -> Result<bool, String> {
+        Ok(self.compare(rhs)? == Some(Ordering::Equal))
+    }
+    // None for functions the type does not know; 'if' is handled by the evaluator
+    fn call(name: &str, args: &[Self]) -> Option<Result<Self, String>>;
+}
+
+pub(crate) fn eval<N: Numeric>(expr: &Expr, source: &str) -> Result<N, Error> {
+    match expr {
+        Expr::Number(pos) => {
+            let text = &without_separators(&source[pos.to_range()]);
+            let value = match text.strip_suffix('%') {
+                Some(percent) => N::literal(percent).and_then(|value| value.div(N::literal("100")?)),
+                None => N::literal(text)
+            };
+            value.map_err(|error| Error { error, at: pos.at, kind: ErrorKind::InvalidNumber })
+        }
+        Expr::Variable(pos) => {
+            let name = &source[pos.to_range()];
+            N::variable(name).ok_or_else(|| Error {
+                error: format!("Unknown variable '{}'", name),
+                at: pos.at,
+                kind: ErrorKind::UnknownVariable
+            })
+        }
+        Expr::Index(element) => Err(Error {
+            error: format!("Indexed variable '{}' is not supported here", &source[element.base.to_range()]),
+            at: element.base.at,
+            kind: ErrorKind::Other
+        }),
+        Expr::Unary{ expr, operator_ix, at } => {
+            let value = eval::<N>(expr, source)?;
+            match operator::from(*operator_ix).char1 {
+                '-' => Ok(value.neg()),
+                '!' => value.truthy().map(|truthy| N::from_bool(!truthy)).map_err(|error| Error { error, at: *at, kind: ErrorKind::Other }),
+                _ => Ok(value)
+            }
+        }
+        Expr::Ternary(ternary) => {
+            let at = |error| Error { error, at: ternary.at, kind: ErrorKind::Other };
+            if eval::<N>(&ternary.condition, source)?.truthy().map_err(at)? {
+                eval(&ternary.then, source)
+            } else {
+                eval(&ternary.otherwise, source)
+            }
+        }
+        Expr::Binary(bin) => {
+            let left = eval::<N>(&bin.left, source)?;
+            let right = eval::<N>(&bin.right, source)?;
+            binary(bin.operator_ix, left, right).map_err(|error| Error { error, at: bin.at, kind: ErrorKind::Other })
+        }
+        Expr::Func(func) => {
+            let FuncExpr { name, params } = &**func;
+            let name_str = &source[name.to_range()];
+            let at_name = |error| Error { error, at: name.at, kind: ErrorKind::Other };
+            if name_str == "if" && params.len() == 3 {
+                return if eval::<N>(&params[0], source)?.truthy().map_err(at_name)? {
+                    eval(&params[1], source)
+                } else {
+                    eval(&params[2], source)
+                };
+            }
+            let args = params.iter().map(|param| eval(param, source)).collect::<Result<Vec<N>, Error>>()?;
+            match N::call(name_str, &args) {
+                Some(result) => result.map_err(at_name),
+                None => Err(Error { kind: ErrorKind::UnknownFunction, ..at_name(format!("Unknown function '{}'", name_str)) })
+            }
+        }
+    }
+}
+
+fn binary<N: Numeric>(operator_ix: u8, left: N, right: N) -> Result<N, String> {
+    let operator = operator::from(operator_ix);
+    if operator.word == Some("xor") {
+        return Ok(N::from_bool(left.truthy()? != right.truthy()?));
+    }
+    match operator.char1 {
+        '&' => Ok(N::from_bool(left.truthy()? && right.truthy()?)),
+        '|' => Ok(N::from_bool(left.truthy()? || right.truthy()?)),
+        '+' => left.add(right),
+        '-' => left.sub(right),
+        '*' => left.mul(right),
+        '/' => left.div(right),
+        '>' if operator.char2 == Some('=') => Ok(N::from_bool(matches!(left.compare(&right)?, Some(Ordering::Greater) | Some(Ordering::Equal)))),
+        '<' if operator.char2 == Some('=') => Ok(N::from_bool(matches!(left.compare(&right)?, Some(Ordering::Less) | Some(Ordering::Equal)))),
+        '>' => Ok(N::from_bool(left.compare(&right)? == Some(Ordering::Greater))),
+        '<' => Ok(N::from_bool(left.compare(&right)? == Some(Ordering::Less))),
+        '=' => Ok(N::from_bool(left.equals(&right)?)),
+        '!' => Ok(N::from_bool(!left.equals(&right)?)),
+        _ => Err(format!("Operator '{}' is not supported", operator))
+    }
+}
@@ -1,17 +1,32 @@
 
 pub fn is_operator(char1: char) -> Option<u8> {
-    OPERATORS.iter().position(|op| op.char1 == char1).map(|pos| pos as u8)
+    OPERATORS.iter().position(|op| op.char1 == char1 && op.char2.is_none() && op.word.is_none()).map(|pos| pos as u8)
 }
 
 pub fn is_multi_char(char1: char, char2: char) -> Option<u8> {
     let char2 = Some(char2);
-    OPERATORS.iter().position(|op| op.char1 == char1 && op.char2 == char2).map(|pos| pos as u8)
+    OPERATORS.iter().position(|op| op.char1 == char1 && op.char2 == char2 && op.word.is_none()).map(|pos| pos as u8)
+}
+
+// operators spelled as words are reserved and cannot be used as identifiers
+pub fn is_keyword(word: &str) -> Option<u8> {
+    OPERATORS.iter().position(|op| op.word == Some(word)).map(|pos| pos as u8)
 }
 
 pub fn from(operator_ix: u8) -> Operator {
     OPERATORS[operator_ix as usize]
 }
 
+// finds an operator by how it is printed, e.g. ">="
+pub fn find(symbol: &str) -> Option<u8> {
+    OPERATORS.iter().position(|op| op.to_string() == symbol).map(|pos| pos as u8)
+}
+
+// for indices that did not come straight from the tokenizer
+pub fn try_from(operator_ix: u8) -> Option<Operator> {
+    OPERATORS.get(operator_ix as usize).copied()
+}
+
 #[derive(Debug, PartialEq)]
 #[derive(Clone, Copy)]
 pub struct Operator {
@@ -19,15 +34,136 @@ pub struct Operator {
     pub char2: Option<char>,
     pub precedence: u8,  
     pub prefix: bool, // can be used as prefix?
+    pub word: Option<&'static str>, // keyword operators like 'xor'
+    pub right_assoc: bool, // `a ^ b ^ c` is `a ^ (b ^ c)`
 }
 
 impl Operator {
     const fn new(char1: char, char2: Option<char>, precedence: u8, prefix: bool) -> Operator {
-        Operator { char1, char2, precedence, prefix }
+        Operator { char1, char2, precedence, prefix, word: None, right_assoc: false }
+    }
+
+    const fn keyword(word: &'static str, precedence: u8) -> Operator {
+        Operator { char1: word.as_bytes()[0] as char, char2: None, precedence, prefix: false, word: Some(word), right_assoc: false }
+    }
+
+    const fn right_associative(self) -> Operator {
+        Operator { right_assoc: true, ..self }
+    }
+
+    // `==` is also an operator of its own, for tables without the default aliases
+    pub fn canonical(self) -> Operator {
+        if self.char1 == '=' && self.char2 == Some('=') { Operator { char2: None, ..self } } else { self }
+    }
+
+    // how far to the right a prefix reaches: `-` and `+` take the rest of the
+    // expression, while `!` stops at any binary operator but `^`, so `!a && b` is `(!a) && b`
+    pub fn prefix_precedence(&self) -> u8 {
+        if self.char1 == '!' && self.char2.is_none() { 60 } else { 0 }
+    }
+
+    // whether it can stand between two operands, the prefix-only ones have no precedence
+    pub fn infix(&self) -> bool {
+        self.precedence > 0
+    }
+
+    // comparisons and logical operators, which always result in 1 or 0
+    pub fn is_boolean(&self) -> bool {
+        self.word == Some("xor") || matches!(self.char1, '<' | '>' | '=' | '!' | '&' | '|')
+    }
+}
+
+impl std::fmt::Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(word) = self.word {
+            return write!(f, "{}", word);
+        }
+        write!(f, "{}", self.char1)?;
+        if let Some(char2) = self.char2 {
+            write!(f, "{}", char2)?;
+        }
+        Ok(())
+    }
+}
+
+/// Alternative spellings the tokenizer translates into canonical operators,
+/// so the parser and evaluator only ever see the operators they know.
+/// The default set maps `==` to `=`, `=>` to `>=`, `=<` to `<=`, `×` to `*` and `÷` to `/`.
+#[derive(Debug, Clone)]
+pub struct OperatorAliases {
+    aliases: Vec<(String, u8)>, // longest spelling first
+    canonical: bool // whether the canonical spellings are recognized too
+}
+
+impl OperatorAliases {
+
+    /// An empty table, leaving only the canonical spellings.
+    pub fn new() -> OperatorAliases {
+        OperatorAliases { aliases: vec![], canonical: true }
+    }
+
+    /// An empty table that replaces the canonical spellings instead of adding
+    /// to them, for defining the whole operator syntax, e.g. of a localized UI.
+    pub fn replacing() -> OperatorAliases {
+        OperatorAliases { aliases: vec![], canonical: false }
+    }
+
+    /// Makes `alias` mean the operator printed as `canonical`. Returns false,
+    /// leaving the table unchanged, if there is no such operator. An alias made
+    /// of letters, like `mul`, is an operator only where it stands as a whole word.
+    pub fn add(&mut self, alias: &str, canonical: &str) -> bool {
+        match find(canonical) {
+            Some(operator_ix) => {
+                self.aliases.retain(|(spelling, _)| spelling != alias);
+                self.aliases.push((alias.to_string(), operator_ix));
+                self.aliases.sort_by_key(|(spelling, _)| std::cmp::Reverse(spelling.len()));
+                true
+            }
+            None => false
+        }
+    }
+
+    // the alias `text` starts with and the canonical operator it stands for
+    pub(crate) fn resolve(&self, text: &str) -> Option<(&str, u8)> {
+        self.aliases.iter()
+            .find(|(spelling, _)| text.starts_with(spelling.as_str()))
+            .map(|(spelling, operator_ix)| (spelling.as_str(), *operator_ix))
+    }
+
+    pub(crate) fn resolve_word(&self, word: &str) -> Option<u8> {
+        self.aliases.iter()
+            .find(|(spelling, _)| spelling == word)
+            .map(|(_, operator_ix)| *operator_ix)
+    }
+
+    pub(crate) fn canonical(&self) -> bool {
+        self.canonical
+    }
+
+    // every spelling taken as an operator, for listing them in error messages
+    pub(crate) fn spellings(&self) -> Vec<String> {
+        let canonical = OPERATORS.iter().filter(|_| self.canonical).map(|op| op.to_string());
+        let mut spellings: Vec<String> = vec![];
+        for spelling in canonical.chain(self.aliases.iter().map(|(spelling, _)| spelling.clone())) {
+            if !spellings.contains(&spelling) {
+                spellings.push(spelling);
+            }
+        }
+        spellings
+    }
+}
+
+impl Default for OperatorAliases {
+    fn default() -> OperatorAliases {
+        let mut aliases = OperatorAliases::new();
+        for (alias, canonical) in &[("==", "="), ("=>", ">="), ("=<", "<="), ("×", "*"), ("÷", "/")] {
+            aliases.add(alias, canonical);
+        }
+        aliases
     }
 }
 
-const OPERATORS: [Operator; 9] = [ 
+const OPERATORS: [Operator; 17] = [ 
     Operator::new('/', None, 60, false),
     Operator::new('*', None, 60, false),
     Operator::new('+', None, 50, true),
@@ -36,5 +172,14 @@ const OPERATORS: [Operator; 9] = [
     Operator::new('>', None, 40, false),
     Operator::new('<', Some('='), 40, false),
     Operator::new('>', Some('='), 40, false),
-    Operator::new('=', None, 30, false)
+    Operator::new('=', None, 30, false),
+    Operator::new('&', Some('&'), 20, false),
+    Operator::keyword("xor", 15),
+    Operator::new('|', Some('|'), 10, false),
+    Operator::new('%', None, 60, false),
+    Operator::new('^', None, 70, false).right_associative(),
+    Operator::new('=', Some('='), 30, false), // same as '='
+    Operator::new('!', Some('='), 30, false),
+    // only a prefix, the lowest precedence stops the parser from taking it as a binary operator
+    Operator::new('!', None, 0, true)
 ];
\ No newline at end of file
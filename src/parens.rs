@@ -0,0 +1,147 @@
+use super::{Error, Position, tokenizer, parser, operator};
+
+// What a parent node needs to know about a child to decide whether the
+// parentheses around it matter.
+#[derive(Clone, Copy)]
+struct Shape {
+    precedence: Option<u8>, // operator at the top of a binary expression
+    unary: bool,
+    paren: Option<Position> // parentheses directly around this node
+}
+
+impl Shape {
+    fn atom() -> Shape {
+        Shape { precedence: None, unary: false, paren: None }
+    }
+}
+
+#[derive(Default)]
+struct Finder {
+    redundant: Vec<Position>
+}
+
+impl Finder {
+    fn report(&mut self, shape: Shape) {
+        if let Some(paren) = shape.paren {
+            self.redundant.push(paren);
+        }
+    }
+}
+
+impl parser::Builder for Finder {
+    type Node = Shape;
+
+    fn number(&mut self, _pos: Position) -> Shape {
+        Shape::atom()
+    }
+
+    fn variable(&mut self, _pos: Position) -> Shape {
+        Shape::atom()
+    }
+
+    // each parameter is delimited by commas anyway
+    fn func(&mut self, _name: Position, params: Vec<Shape>) -> Shape {
+        for param in params {
+            self.report(param);
+        }
+        Shape::atom()
+    }
+
+    // '?' and ':' delimit the branches; the condition only needs parentheses
+    // if it is a ternary itself or starts with a prefix operator, which would take the rest
+    fn ternary(&mut self, condition: Shape, _at: u32, then: Shape, otherwise: Shape) -> Shape {
+        if condition.precedence != Some(0) && !condition.unary {
+            self.report(condition);
+        }
+        self.report(then);
+        self.report(otherwise);
+        Shape { precedence: Some(0), unary: false, paren: None }
+    }
+
+    // the brackets delimit the index
+    fn index(&mut self, _base: Position, index: Shape) -> Shape {
+        self.report(index);
+        Shape::atom()
+    }
+
+    // a prefix operator takes everything after it, so parentheses around
+    // or directly below it are kept
+    fn unary(&mut self, _at: u32, _operator_ix: u8, _expr: Shape) -> Shape {
+        Shape { precedence: None, unary: true, paren: None }
+    }
+
+    // operators of equal precedence group on the side they associate to, so only
+    // that operand can drop parentheses around an operator of the same precedence,
+    // the left one for most operators and the right one for `^`
+    fn binary(&mut self, left: Shape, _at: u32, operator_ix: u8, right: Shape) -> Shape {
+        let operator = operator::from(operator_ix);
+        let precedence = operator.precedence;
+        if matches!(left.precedence, Some(left_prec) if left_prec > precedence || (left_prec == precedence && !operator.right_assoc)) {
+            self.report(left);
+        }
+        if matches!(right.precedence, Some(right_prec) if right_prec > precedence || (right_prec == precedence && operator.right_assoc)) {
+            self.report(right);
+        }
+        Shape { precedence: Some(precedence), unary: false, paren: None }
+    }
+
+    fn group(&mut self, open: u32, close: u32, expr: Shape) -> Shape {
+        let paren = Position { at: open, len: (close - open + 1) as u16 };
+        if expr.paren.is_some() || (expr.precedence.is_none() && !expr.unary) {
+            // doubled parentheses and parentheses around a single value never matter
+            self.redundant.push(paren);
+            expr
+        } else {
+            Shape { paren: Some(paren), ..expr }
+        }
+    }
+}
+
+/// Finds parentheses that can be removed without changing how the expression
+/// is evaluated, e.g. in `(1) + 2` and `1 + (2 * 3)`, sorted by position.
+pub fn redundant_parens(expression: &str) -> Result<Vec<Position>, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let mut finder = Finder::default();
+    let root = parser::parse_with(&mut tokens, &mut finder)?;
+    finder.report(root);
+    let mut redundant = finder.redundant;
+    redundant.sort_by_key(|paren| paren.at);
+    Ok(redundant)
+}
+
+
+#[cfg(test)]
+mod parens_should {
+    use super::*;
+
+    fn redundant(expression: &str) -> Vec<&str> {
+        redundant_parens(expression).unwrap()
+            .into_iter()
+            .map(|paren| &expression[paren.to_range()])
+            .collect()
+    }
+
+    #[test]
+    fn keep_needed_parens() {
+        assert!(redundant("(1 + 2) * 3").is_empty());
+        assert!(redundant("1 - (2 - 3)").is_empty());
+        assert!(redundant("(2 ^ 3) ^ 2").is_empty());
+        assert!(redundant("-(1 + 2) * 3").is_empty());
+        assert!(redundant("(a ? 1 : 2) ? 3 : 4").is_empty());
+        assert!(redundant("(-a) ? 1 : 2").is_empty());
+        assert!(redundant("1 + (a ? 1 : 2)").is_empty());
+    }
+
+    #[test]
+    fn report_redundant_parens() {
+        assert_eq!(redundant("(1) + 2"), vec!["(1)"]);
+        assert_eq!(redundant("1 + (2 * 3)"), vec!["(2 * 3)"]);
+        assert_eq!(redundant("(1 - 2) - 3"), vec!["(1 - 2)"]);
+        assert_eq!(redundant("2 ^ (3 ^ 2)"), vec!["(3 ^ 2)"]);
+        assert_eq!(redundant("(1 + 2)"), vec!["(1 + 2)"]);
+        assert_eq!(redundant("max((1 + 2), (x))"), vec!["(1 + 2)", "(x)"]);
+        assert_eq!(redundant("((1))"), vec!["((1))", "(1)"]);
+        assert_eq!(redundant("((1 + 2)) * 3"), vec!["((1 + 2))"]);
+        assert_eq!(redundant("(a > 0) ? (1) : (b ? 2 : 3)"), vec!["(a > 0)", "(1)", "(b ? 2 : 3)"]);
+    }
+}
@@ -1,10 +1,70 @@
 use crate::tokenizer::{ Token };
-use super::{Expr, Error, BinaryExpr, FuncExpr};
+use super::{Expr, Error, ErrorKind, BinaryExpr, FuncExpr, IndexExpr, TernaryExpr, Position, operator, valid_operator};
 use std::iter::Peekable;
-use crate::operator as operator;
 
 
+/// Constructs tree nodes as the parser recognizes them, so the same
+/// grammar can produce either a boxed `Expr` or an arena of indexed nodes.
+pub trait Builder {
+    type Node;
+    fn number(&mut self, pos: Position) -> Self::Node;
+    fn variable(&mut self, pos: Position) -> Self::Node;
+    fn func(&mut self, name: Position, params: Vec<Self::Node>) -> Self::Node;
+    fn index(&mut self, base: Position, index: Self::Node) -> Self::Node;
+    fn unary(&mut self, at: u32, operator_ix: u8, expr: Self::Node) -> Self::Node;
+    fn binary(&mut self, left: Self::Node, at: u32, operator_ix: u8, right: Self::Node) -> Self::Node;
+    fn ternary(&mut self, condition: Self::Node, at: u32, then: Self::Node, otherwise: Self::Node) -> Self::Node;
+    // grouping is implicit in the tree shape, so most builders drop the parentheses
+    fn group(&mut self, _open: u32, _close: u32, expr: Self::Node) -> Self::Node {
+        expr
+    }
+}
+
+pub struct BoxBuilder;
+
+impl Builder for BoxBuilder {
+    type Node = Expr;
+
+    fn number(&mut self, pos: Position) -> Expr {
+        Expr::Number(pos)
+    }
+
+    fn variable(&mut self, pos: Position) -> Expr {
+        Expr::Variable(pos)
+    }
+
+    fn func(&mut self, name: Position, params: Vec<Expr>) -> Expr {
+        Expr::Func(Box::new(FuncExpr { name, params }))
+    }
+
+    fn index(&mut self, base: Position, index: Expr) -> Expr {
+        Expr::Index(Box::new(IndexExpr { base, index }))
+    }
+
+    fn unary(&mut self, at: u32, operator_ix: u8, expr: Expr) -> Expr {
+        Expr::Unary { expr: Box::new(expr), operator_ix, at }
+    }
+
+    fn binary(&mut self, left: Expr, at: u32, operator_ix: u8, right: Expr) -> Expr {
+        Expr::Binary(Box::new(BinaryExpr { left, operator_ix, right, at }))
+    }
+
+    fn ternary(&mut self, condition: Expr, at: u32, then: Expr, otherwise: Expr) -> Expr {
+        Expr::Ternary(Box::new(TernaryExpr { condition, at, then, otherwise }))
+    }
+}
+
 pub fn parse(tokens: &mut impl Iterator<Item = Result<Token,Error>>) -> Result<Expr, Error> {
+    parse_with(tokens, &mut BoxBuilder)
+}
+
+pub fn parse_with<B: Builder>(tokens: &mut impl Iterator<Item = Result<Token,Error>>, builder: &mut B) -> Result<B::Node, Error> {
+    parse_with_limit(tokens, builder, u32::MAX)
+}
+
+/// Like `parse_with`, but fails as soon as operations, calls or parentheses
+/// nest deeper than `max_depth`, before the recursion can exhaust the stack.
+pub fn parse_with_limit<B: Builder>(tokens: &mut impl Iterator<Item = Result<Token,Error>>, builder: &mut B, max_depth: u32) -> Result<B::Node, Error> {
     let mut has_error:Option<Error> = None;
     let mut enumerator = tokens
         .scan(&mut has_error, |err, res| match res {
@@ -15,33 +75,84 @@ pub fn parse(tokens: &mut impl Iterator<Item = Result<Token,Error>>) -> Result<E
             }
         })
         .peekable();
-    let result = expr(&mut enumerator, 0);
-    // check unconsumed tokens
-    if let Some(token) = enumerator.next() {
-        return error("Unexpected token ", token);
-    }
-    // check for errors
+    let empty = enumerator.peek().is_none();
+    let result = expr(&mut enumerator, builder, Depth { level: 0, max: max_depth });
+    let unconsumed = enumerator.next();
+    // check for errors, tokenizer errors first since they cut the token stream short
     if let Some(err) = has_error {
         return Err(err);
     }
+    if empty {
+        return Err(Error {
+            error: "Expression is empty".to_string(),
+            at: 0,
+            kind: ErrorKind::EmptyInput
+        });
+    }
+    let result = result?;
+    // check unconsumed tokens
+    if let Some(token) = unconsumed {
+        // a closer left over at the top level has no opening one
+        let (message, kind) = match token {
+            Token::RParen(_) => ("Unmatched closing parenthesis ')'", ErrorKind::UnbalancedParens),
+            Token::RBracket(_) => ("Unmatched closing bracket ']'", ErrorKind::UnbalancedParens),
+            _ => ("Unexpected token ", ErrorKind::UnexpectedToken)
+        };
+        return error(message, kind, token);
+    }
     
-    result
+    Ok(result)
 }
 
-fn expr(tokens: &mut Peekable<impl Iterator<Item=Token>>, precedence: u8) -> Result<Expr, Error> {
-    let mut left = singular(tokens);
+// a whole expression, at the top or inside parentheses, brackets or a call
+fn expr<B: Builder>(tokens: &mut Peekable<impl Iterator<Item=Token>>, builder: &mut B, depth: Depth) -> Result<B::Node, Error> {
+    let condition = nested(tokens, builder, 0, depth)?;
+    // `cond ? a : b` binds looser than any operator, so only a whole expression can be its condition
+    let at = match tokens.peek() {
+        Some(&Token::Question(at)) => at,
+        _ => return Ok(condition)
+    };
+    tokens.next();
+    let depth = depth.enter(at)?;
+    let then = expr(tokens, builder, depth)?;
+    match tokens.next() {
+        Some(Token::Colon(_)) => {}
+        Some(token) => return error("Expected ':' but found ", ErrorKind::UnexpectedToken, token),
+        None => return Err(Error {
+            error: "Expected ':' after '?' but reached the end".to_string(),
+            at,
+            kind: ErrorKind::UnexpectedEof
+        })
+    }
+    // the false branch extends to the right, so `a ? b : c ? d : e` nests like `if`
+    let otherwise = expr(tokens, builder, depth)?;
+    Ok(builder.ternary(condition, at, then, otherwise))
+}
+
+// the operations binding tighter than `precedence`, one level deeper than `depth`;
+// as the operand of an operator it cannot be a `cond ? a : b` without parentheses
+fn nested<B: Builder>(tokens: &mut Peekable<impl Iterator<Item=Token>>, builder: &mut B, precedence: u8, depth: Depth) -> Result<B::Node, Error> {
+    let depth = depth.enter(tokens.peek().map_or(0, |token| token.at()))?;
+    operations(tokens, builder, precedence, depth)
+}
+
+fn operations<B: Builder>(tokens: &mut Peekable<impl Iterator<Item=Token>>, builder: &mut B, precedence: u8, depth: Depth) -> Result<B::Node, Error> {
+    let mut left = singular(tokens, builder, depth);
+    let mut chained = 0;
     while let Some(&token) = tokens.peek() {
         match token {
-            Token::Operator {at:_, operator_ix} => {
-                let new_prec = operator::from(operator_ix).precedence;
+            Token::Operator {at, operator_ix} => {
+                let operator = valid_operator(operator_ix, at)?;
+                let new_prec = operator.precedence;
                 if  new_prec > precedence {
+                    // each operation chained to the left wraps the ones before it one level deeper
+                    chained += 1;
+                    Depth { level: depth.level + chained, ..depth }.check(at)?;
                     tokens.next();
-                    let right = expr(tokens, new_prec);
-                    left = Ok(Expr::Binary(Box::new(BinaryExpr {
-                        left: left?,
-                        operator_ix,
-                        right: right?
-                    })))
+                    // a right associative operator lets an equal one continue its right side
+                    let right_prec = if operator.right_assoc { new_prec - 1 } else { new_prec };
+                    let right = operand(tokens, builder, at, operator_ix, right_prec, depth);
+                    left = Ok(builder.binary(left?, at, operator_ix, right?))
                 } else {
                     return left
                 }
@@ -53,61 +164,114 @@ fn expr(tokens: &mut Peekable<impl Iterator<Item=Token>>, precedence: u8) -> Res
 }
 
 
-fn singular(tokens: &mut Peekable<impl Iterator<Item=Token>>) -> Result<Expr, Error> {
+fn singular<B: Builder>(tokens: &mut Peekable<impl Iterator<Item=Token>>, builder: &mut B, depth: Depth) -> Result<B::Node, Error> {
     if let Some(&token) = tokens.peek() {
         match token {
-            Token::Operator{ at:_, operator_ix } => {
+            Token::Operator{ at, operator_ix } => {
+                let operator = valid_operator(operator_ix, at)?;
+                if !operator.prefix {
+                    return Err(Error {
+                        error: format!("Operator '{}' cannot be used as a prefix", operator),
+                        at,
+                        kind: ErrorKind::UnexpectedToken
+                    });
+                }
                 tokens.next();
-                Ok(Expr::Unary{
-                    operator_ix, 
-                    expr: Box::new(expr(tokens, 0)?)
-                })
+                let expr = operand(tokens, builder, at, operator_ix, operator.prefix_precedence(), depth)?;
+                Ok(builder.unary(at, operator_ix, expr))
             },
             Token::Str(name) => {
                 tokens.next(); //consume STRING
                 //string followed by left parenth is a function
                 match tokens.peek() {
-                    Some(Token::LParen(_)) => {
-                        Ok(Expr::Func(Box::new(FuncExpr {
-                            name, 
-                            params: params(tokens)?
-                        })))
+                    Some(&Token::LParen(open)) => {
+                        let params = params(tokens, builder, open, depth)?;
+                        Ok(builder.func(name, params))
+                    },
+                    // `v[i]`, an element of an indexed variable
+                    Some(&Token::LBracket(open)) => {
+                        tokens.next();
+                        let index = expr(tokens, builder, depth)?;
+                        match tokens.next() {
+                            Some(Token::RBracket(_)) => Ok(builder.index(name, index)),
+                            Some(token) => error("Expected closing bracket ']' but found ", ErrorKind::UnexpectedToken, token),
+                            None => Err(Error {
+                                error: "Missing closing bracket ']'".to_string(),
+                                at: open,
+                                kind: ErrorKind::UnbalancedParens
+                            })
+                        }
                     },
                     _ => {
-                        Ok(Expr::Variable(name))
+                        Ok(builder.variable(name))
                     }
                 }
             },
-            Token::LParen(_) => parentheses(tokens),
+            Token::LParen(open) => parentheses(tokens, builder, open, depth),
+            // `[1, 2, 3]` is built as a call of the function named `[`
+            #[cfg(feature = "vectors")]
+            Token::LBracket(open) => {
+                let elements = elements(tokens, builder, open, depth)?;
+                Ok(builder.func(Position { at: open, len: 1 }, elements))
+            },
             Token::Number(pos) => {
-                let number = Ok(Expr::Number(pos));
+                let number = Ok(builder.number(pos));
                 tokens.next();
                 number
             },
-            _ => error("Expected operator, variable, function or number but found ", token)
+            _ => error("Expected operator, variable, function or number but found ", ErrorKind::UnexpectedToken, token)
         }
     } else {
         Err(Error {
             error: "Expected expression but reached the end".to_string(),
-            at: 0
+            at: 0,
+            kind: ErrorKind::UnexpectedEof
         })
     }
 }
 
-fn parentheses(tokens: &mut Peekable<impl Iterator<Item=Token>>) -> Result<Expr, Error> {
+// the right side of an operator; a missing one gets an error pointing at the operator
+fn operand<B: Builder>(tokens: &mut Peekable<impl Iterator<Item=Token>>, builder: &mut B, at: u32, operator_ix: u8, precedence: u8, depth: Depth) -> Result<B::Node, Error> {
+    match tokens.peek() {
+        None => return Err(Error {
+            error: format!("Expected a value after '{}' but reached the end", operator::from(operator_ix)),
+            at,
+            kind: ErrorKind::UnexpectedEof
+        }),
+        // most likely a typo such as `*/`
+        Some(&Token::Operator { at: next_at, operator_ix: next_ix }) if !valid_operator(next_ix, next_at)?.prefix => return Err(Error {
+            error: format!("Two operators in a row: '{}' then '{}'", operator::from(operator_ix), operator::from(next_ix)),
+            at: next_at,
+            kind: ErrorKind::UnexpectedToken
+        }),
+        _ => {}
+    }
+    nested(tokens, builder, precedence, depth)
+}
+
+fn parentheses<B: Builder>(tokens: &mut Peekable<impl Iterator<Item=Token>>, builder: &mut B, open: u32, depth: Depth) -> Result<B::Node, Error> {
     tokens.next(); // consume left parenthesis
-    let expr = expr(tokens, 0)?;
+    // `f()` is a call without arguments, but `()` alone has nothing to group
+    if let Some(Token::RParen(_)) = tokens.peek() {
+        return Err(Error {
+            error: "Empty parentheses are not a valid expression".to_string(),
+            at: open,
+            kind: ErrorKind::UnexpectedToken
+        });
+    }
+    let expr = expr(tokens, builder, depth)?;
     match tokens.next() {
-        Some(Token::RParen(..)) => Ok(expr),
-        Some(token) => error("Expected closing parenthesis ')' but found ", token),
+        Some(Token::RParen(close)) => Ok(builder.group(open, close, expr)),
+        Some(token) => error("Expected closing parenthesis ')' but found ", ErrorKind::UnexpectedToken, token),
         None => Err(Error {
             error: "Missing closing parenthesis ')'".to_string(),
-            at: 0
+            at: open,
+            kind: ErrorKind::UnbalancedParens
         })
     }
 }
 
-fn params(tokens: &mut Peekable<impl Iterator<Item=Token>>) -> Result<Vec<Expr>, Error> {
+fn params<B: Builder>(tokens: &mut Peekable<impl Iterator<Item=Token>>, builder: &mut B, open: u32, depth: Depth) -> Result<Vec<B::Node>, Error> {
     tokens.next(); // consume left parenthesis
     let mut vec = vec![];
     // function may have any number of parameters separated by comma
@@ -120,36 +284,85 @@ fn params(tokens: &mut Peekable<impl Iterator<Item=Token>>) -> Result<Vec<Expr>,
             },
             Some(Token::Comma(..)) => {
                 tokens.next();
-                vec.push(expr(tokens, 0)?);
+                vec.push(expr(tokens, builder, depth)?);
             },
-            Some(_) => vec.push(expr(tokens, 0)?),
+            Some(_) => vec.push(expr(tokens, builder, depth)?),
             None => return Err(Error {
                 error: "Missing closing parenthesis ')'".to_string(),
-                at: 0
+                at: open,
+                kind: ErrorKind::UnbalancedParens
             })
         };
     }
     
 }
 
-fn error(error: &str, _token:Token) -> Result<Expr, Error> {
+#[cfg(feature = "vectors")]
+fn elements<B: Builder>(tokens: &mut Peekable<impl Iterator<Item=Token>>, builder: &mut B, open: u32, depth: Depth) -> Result<Vec<B::Node>, Error> {
+    tokens.next(); // consume left bracket
+    let mut vec = vec![];
+    if let Some(Token::RBracket(_)) = tokens.peek() {
+        tokens.next();
+        return Ok(vec);
+    }
+    loop {
+        vec.push(expr(tokens, builder, depth)?);
+        match tokens.next() {
+            Some(Token::Comma(_)) => {},
+            Some(Token::RBracket(_)) => return Ok(vec),
+            Some(token) => return error("Expected ',' or ']' but found ", ErrorKind::UnexpectedToken, token),
+            None => return Err(Error {
+                error: "Missing closing bracket ']'".to_string(),
+                at: open,
+                kind: ErrorKind::UnbalancedParens
+            })
+        }
+    }
+}
+
+// how deeply the expression being parsed is nested, every operand is one level
+#[derive(Clone, Copy)]
+struct Depth {
+    level: u32,
+    max: u32
+}
+
+impl Depth {
+    fn enter(self, at: u32) -> Result<Depth, Error> {
+        Depth { level: self.level + 1, ..self }.check(at)
+    }
+
+    fn check(self, at: u32) -> Result<Depth, Error> {
+        if self.level > self.max {
+            return Err(Error {
+                error: format!("Expression is nested deeper than {}", self.max),
+                at,
+                kind: ErrorKind::Other
+            });
+        }
+        Ok(self)
+    }
+}
+
+fn error<T>(error: &str, kind: ErrorKind, token: Token) -> Result<T, Error> {
     Err(Error {
         error: error.to_string(),
-        at: 0
+        at: token.at(),
+        kind
     })
 }
 
 #[cfg(test)]
 mod parse_should {
     use super::*;
-    use super::super::Position;
     use crate::operator as operator;
 
     const NUMBER: Result<Token,Error> = Ok(Token::Number(Position { at: 0, len: 0 }));
     const STRING: Result<Token,Error> = Ok(Token::Str(Position { at: 0, len: 0 }));
     const L_PAREN: Result<Token,Error> = Ok(Token::LParen(0));
     const R_PAREN: Result<Token,Error> = Ok(Token::RParen(0));
-    const OPERATOR: Result<Token,Error> = Ok(Token::Operator { at: 0, operator_ix: 0 });
+    // '+' can be used both as a prefix and as a binary operator
+    const OPERATOR: Result<Token,Error> = Ok(Token::Operator { at: 0, operator_ix: 2 });
 
     #[test]
     fn handle_numbers() {
@@ -166,15 +379,23 @@ mod parse_should {
     #[test]
     fn handle_nested_unary() {
         let mut tokens = vec![OPERATOR, OPERATOR, NUMBER].into_iter();
-        if let Ok(Expr::Unary{expr:unary, operator_ix:_}) = parse(&mut tokens) {
-            if let Expr::Unary{expr:num, operator_ix:_} = *unary {
+        if let Ok(Expr::Unary{expr:unary, operator_ix:_, at:_}) = parse(&mut tokens) {
+            if let Expr::Unary{expr:num, operator_ix:_, at:_} = *unary {
                 assert_matches!(*num, Expr::Number(..));
                 return;
             }
         }
-        assert!(false);
+        panic!();
     }
     
+    #[test]
+    fn error_on_non_prefix_operator() {
+        let mul = Ok(Token::Operator{at: 0, operator_ix: operator::is_operator('*').unwrap()});
+        let mut tokens = vec![mul, NUMBER].into_iter();
+        let expr = parse(&mut tokens);
+        assert!(matches!(expr, Err(e) if e.error == "Operator '*' cannot be used as a prefix"));
+    }
+
     #[test]
     fn handle_parentheses() {
         let mut tokens = vec![L_PAREN, NUMBER, R_PAREN].into_iter();
@@ -201,7 +422,18 @@ mod parse_should {
             assert_matches!(expr.left, Expr::Binary(..));        
             assert_matches!(expr.right, Expr::Number(..));
         }
-        else { assert!(false) }
+        else { panic!() }
+    }
+
+    #[test]
+    fn group_right_associative_operators_to_the_right() {
+        let power = Token::Operator { at: 0, operator_ix: operator::find("^").unwrap() };
+        let mut tokens = vec![NUMBER, Ok(power), NUMBER, Ok(power), NUMBER].into_iter();
+        if let Ok(Expr::Binary(bin_expr)) = parse(&mut tokens) {
+            assert_matches!(bin_expr.left, Expr::Number(..));
+            assert_matches!(bin_expr.right, Expr::Binary(..));
+        }
+        else { panic!() }
     }
 
     #[test]
@@ -211,6 +443,27 @@ mod parse_should {
         assert_matches!(expr, Expr::Variable(..));
     }
 
+    #[test]
+    fn nest_ternary_to_the_right() {
+        let mut tokens = vec![NUMBER, Ok(Token::Question(0)), NUMBER, Ok(Token::Colon(0)),
+                              NUMBER, Ok(Token::Question(0)), NUMBER, Ok(Token::Colon(0)), NUMBER].into_iter();
+        match parse(&mut tokens) {
+            Ok(Expr::Ternary(ternary)) => {
+                assert_matches!(ternary.then, Expr::Number(..));
+                assert_matches!(ternary.otherwise, Expr::Ternary(..));
+            }
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn handle_indexed_variable() {
+        let mut tokens = vec![STRING, Ok(Token::LBracket(1)), NUMBER, Ok(Token::RBracket(3))].into_iter();
+        assert_matches!(parse(&mut tokens), Ok(Expr::Index(element)) if matches!(element.index, Expr::Number(..)));
+        let mut tokens = vec![STRING, Ok(Token::LBracket(1)), NUMBER].into_iter();
+        assert_matches!(parse(&mut tokens), Err(..));
+    }
+
     #[test]
     fn handle_func_no_params() {
         let mut tokens = vec![STRING, L_PAREN, R_PAREN].into_iter();
@@ -231,7 +484,7 @@ mod parse_should {
             let FuncExpr{name:_, params} = *boxed;
             assert_eq!(params.len(), 2);
         }
-        else { assert!(false) }
+        else { panic!() }
     }
 
     #[test]
@@ -244,13 +497,13 @@ mod parse_should {
             NUMBER
         ].into_iter();
         if let Expr::Binary(bin1) = parse(&mut tokens).unwrap() {
-            if let Expr::Binary(bin2) = (*bin1).right {
+            if let Expr::Binary(bin2) = bin1.right {
                 let bin2 = *bin2;
                 assert_eq!(operator::from(bin1.operator_ix).char1, '+');
                 assert_eq!(operator::from(bin2.operator_ix).char1, '*');
             }
         } else {
-            assert!(false);
+            panic!();
         }
     }
 
@@ -268,6 +521,56 @@ mod parse_should {
         assert_matches!(expr, Err(..));
     }
 
+    #[test]
+    fn report_position_of_unclosed_parenthesis() {
+        let error = parse(&mut crate::tokenizer::Tokens::new("2 * (1 + 3")).unwrap_err();
+        assert_eq!(error.at, 4);
+        let error = parse(&mut crate::tokenizer::Tokens::new("1 + max(2, 3")).unwrap_err();
+        assert_eq!(error.at, 7);
+        let error = parse(&mut crate::tokenizer::Tokens::new("v[1 + 2")).unwrap_err();
+        assert_eq!(error.at, 1);
+    }
+
+    #[test]
+    fn report_position_of_unbalanced_parenthesis() {
+        let error = parse(&mut crate::tokenizer::Tokens::new("(1 + (2 * 3)")).unwrap_err();
+        assert_eq!((error.error.as_str(), error.at), ("Missing closing parenthesis ')'", 0));
+        let error = parse(&mut crate::tokenizer::Tokens::new("(1 + 2) * (3")).unwrap_err();
+        assert_eq!(error.at, 10);
+        let error = parse(&mut crate::tokenizer::Tokens::new("1 + 2)")).unwrap_err();
+        assert_eq!((error.error.as_str(), error.at), ("Unmatched closing parenthesis ')'", 5));
+        let error = parse(&mut crate::tokenizer::Tokens::new("(1) + 2) * 3")).unwrap_err();
+        assert_eq!(error.at, 7);
+    }
+
+    #[test]
+    fn error_on_empty_parentheses() {
+        for (expression, at) in &[("()", 0), ("1 + ()", 4)] {
+            let error = parse(&mut crate::tokenizer::Tokens::new(expression)).unwrap_err();
+            assert_eq!(error.error, "Empty parentheses are not a valid expression");
+            assert_eq!(error.at, *at);
+        }
+        assert_matches!(parse(&mut crate::tokenizer::Tokens::new("f()")), Ok(Expr::Func(..)));
+    }
+
+    #[test]
+    fn report_position_of_unexpected_token() {
+        let error = parse(&mut crate::tokenizer::Tokens::new("(1 + 2) 3")).unwrap_err();
+        assert_eq!(error.at, 8);
+        let error = parse(&mut crate::tokenizer::Tokens::new("a ? 1 , 2")).unwrap_err();
+        assert_eq!(error.at, 6);
+    }
+
+    #[test]
+    fn error_on_empty_input() {
+        for expression in &["", "   "] {
+            let error = parse(&mut crate::tokenizer::Tokens::new(expression)).unwrap_err();
+            assert_eq!((error.error.as_str(), error.kind), ("Expression is empty", ErrorKind::EmptyInput));
+        }
+        let error = parse(&mut crate::tokenizer::Tokens::new("1 +")).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnexpectedEof);
+    }
+
     #[test]
     fn error_on_incomplete() {
         let mut tokens = vec![NUMBER, OPERATOR].into_iter();
@@ -275,14 +578,18 @@ mod parse_should {
         assert_matches!(expr, Err(..));
     }
 
+    #[test]
+    fn error_on_invalid_operator_index() {
+        let invalid = Ok(Token::Operator { at: 2, operator_ix: 200 });
+        let mut tokens = vec![NUMBER, invalid, NUMBER].into_iter();
+        assert!(matches!(parse(&mut tokens), Err(e) if e.at == 2));
+    }
+
     #[test]
     fn report_tokenizer_error() {
-        let error:Result<Token,Error> = Err(Error{error:"tokenizer".to_string(), at:0});
+        let error:Result<Token,Error> = Err(Error{error:"tokenizer".to_string(), at:0, kind: ErrorKind::InvalidNumber});
         let mut tokens = vec![NUMBER, error, STRING].into_iter();
         let expr = parse(&mut tokens);
-        assert!(match expr {
-            Err(e) if e.error.contains("tokenizer") => true,
-            _ => false
-        });
+        assert!(matches!(expr, Err(e) if e.kind == ErrorKind::InvalidNumber));
     }
 }
\ No newline at end of file
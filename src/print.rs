@@ -0,0 +1,116 @@
+use super::{Expr, Expression, SourcedExpr, operator};
+use std::fmt;
+
+/// Prints the expression with single spaces around operators and only the
+/// parentheses the tree needs, e.g. `(1+2)*((3))` as `(1 + 2) * 3`.
+impl fmt::Display for SourcedExpr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_expr(f, self.expr(), self.source())
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_expr(f, self.expr(), self.source())
+    }
+}
+
+fn write_expr(f: &mut fmt::Formatter, expr: &Expr, source: &str) -> fmt::Result {
+    match expr {
+        Expr::Number(pos) | Expr::Variable(pos) => f.write_str(&source[pos.to_range()]),
+        Expr::Func(func) => {
+            // a vector literal is a call of the function named `[`
+            let name = &source[func.name.to_range()];
+            let (open, close) = if name == "[" { ("", "]") } else { ("(", ")") };
+            write!(f, "{}{}", name, open)?;
+            for (ix, param) in func.params.iter().enumerate() {
+                if ix > 0 {
+                    f.write_str(", ")?;
+                }
+                write_expr(f, param, source)?;
+            }
+            f.write_str(close)
+        }
+        Expr::Index(element) => {
+            write!(f, "{}[", &source[element.base.to_range()])?;
+            write_expr(f, &element.index, source)?;
+            f.write_str("]")
+        }
+        // a prefix operator takes everything after it, so an operation below it is parenthesized
+        Expr::Unary { expr, operator_ix, .. } => {
+            write!(f, "{}", operator::from(*operator_ix))?;
+            write_operand(f, expr, source, matches!(**expr, Expr::Binary(_) | Expr::Ternary(_)))
+        }
+        Expr::Binary(bin) => {
+            let operator = operator::from(bin.operator_ix);
+            write_operand(f, &bin.left, source, binds_looser(&bin.left, operator.precedence, operator.right_assoc))?;
+            write!(f, " {} ", operator)?;
+            write_operand(f, &bin.right, source, binds_looser(&bin.right, operator.precedence, !operator.right_assoc))
+        }
+        Expr::Ternary(ternary) => {
+            write_operand(f, &ternary.condition, source, matches!(ternary.condition, Expr::Unary { .. } | Expr::Ternary(_)))?;
+            f.write_str(" ? ")?;
+            write_expr(f, &ternary.then, source)?;
+            f.write_str(" : ")?;
+            write_expr(f, &ternary.otherwise, source)
+        }
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter, expr: &Expr, source: &str, parens: bool) -> fmt::Result {
+    if parens {
+        f.write_str("(")?;
+        write_expr(f, expr, source)?;
+        f.write_str(")")
+    } else {
+        write_expr(f, expr, source)
+    }
+}
+
+// whether an operand of an operator with `precedence` needs parentheses;
+// `on_grouping_side` is false on the side an equal operator groups towards
+fn binds_looser(operand: &Expr, precedence: u8, on_grouping_side: bool) -> bool {
+    match operand {
+        Expr::Binary(bin) => {
+            let operand_precedence = operator::from(bin.operator_ix).precedence;
+            operand_precedence < precedence || (operand_precedence == precedence && on_grouping_side)
+        }
+        Expr::Unary { .. } | Expr::Ternary(_) => true,
+        _ => false
+    }
+}
+
+
+#[cfg(test)]
+mod print_should {
+    use crate::{parse, evaluate_with, Expression};
+    use std::collections::HashMap;
+
+    fn reprint(expression: &str) -> String {
+        parse(expression).unwrap().to_string()
+    }
+
+    #[test]
+    fn keep_only_needed_parentheses() {
+        assert_eq!(reprint("1+2*3"), "1 + 2 * 3");
+        assert_eq!(reprint("(1+2)*((3))"), "(1 + 2) * 3");
+        assert_eq!(reprint("1 - (2 - 3)"), "1 - (2 - 3)");
+        assert_eq!(reprint("(1 - 2) - 3"), "1 - 2 - 3");
+        assert_eq!(reprint("(2 ^ 3) ^ 2"), "(2 ^ 3) ^ 2");
+        assert_eq!(reprint("2 ^ (3 ^ 2)"), "2 ^ 3 ^ 2");
+        assert_eq!(reprint("-(1 + 2) * x"), "-((1 + 2) * x)");
+        assert_eq!(reprint("(-1) + 2"), "(-1) + 2");
+        assert_eq!(reprint("max(a,v[i+1])"), "max(a, v[i + 1])");
+        assert_eq!(reprint("(a ? 1 : 2) ? 3 : b?4:5"), "(a ? 1 : 2) ? 3 : b ? 4 : 5");
+        assert_eq!(Expression::compile("1+ 1").unwrap().to_string(), "1 + 1");
+    }
+
+    #[test]
+    fn reprint_to_the_same_value() {
+        let vars: HashMap<&str, f32> = [("a", 2.0), ("b", -1.0)].iter().copied().collect();
+        for expression in &["1 + 2 * 3", "(1 + 2) * 3", "2 ^ -1 - (a - b)", "(-a) * b", "-a * b", "!(a > b) || a", "a > 1 ? a : b"] {
+            let reprinted = reprint(expression);
+            assert_eq!(evaluate_with(&reprinted, &vars).unwrap(), evaluate_with(expression, &vars).unwrap(), "{}", reprinted);
+        }
+    }
+}
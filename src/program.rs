@@ -0,0 +1,153 @@
+use super::{Error, ErrorKind, Expr, Context, Functions, IdentKind, Keyword, tokenizer, parser, eval_expr, classify_identifier};
+use tokenizer::{Token, Tokens};
+use std::collections::HashMap;
+
+// deep enough for reasonable recursion, shallow enough not to overflow the stack
+const MAX_CALL_DEPTH: u32 = 64;
+
+struct Def {
+    params: Vec<String>,
+    body: Expr
+}
+
+/// Function definitions followed by an expression using them, separated by
+/// semicolons: `def sq(x) = x * x; sq(4)`. Definitions can call each other
+/// and themselves, with calls nested at most 64 deep.
+pub struct Program {
+    source: String,
+    defs: HashMap<String, Def>,
+    main: Expr
+}
+
+impl Program {
+
+    pub fn parse(source: &str) -> Result<Program, Error> {
+        let mut defs = HashMap::new();
+        let mut start = 0;
+        for (end, _) in source.match_indices(';') {
+            let (name, def) = definition(&source[..end], start as u32)?;
+            if defs.insert(name.clone(), def).is_some() {
+                return Err(Error {
+                    error: format!("Function '{}' is already defined", name),
+                    at: start as u32,
+                    kind: ErrorKind::Other
+                });
+            }
+            start = end + 1;
+        }
+        let main = parser::parse(&mut Tokens::new(source).skip_to(start as u32))?;
+        Ok(Program { source: source.to_string(), defs, main })
+    }
+
+    pub fn eval(&self) -> Result<f32, Error> {
+        let calls = Calls { program: self, depth: 0 };
+        eval_expr(&self.main, &Context::new(&self.source, &|_| None).with_functions(&calls))
+    }
+}
+
+// parses `def name(a, b) = body` from the part of source after start
+fn definition(source: &str, start: u32) -> Result<(String, Def), Error> {
+    let mut tokens = Tokens::new(source).skip_to(start);
+    let error = |error: String, at: u32| Err(Error { error, at, kind: ErrorKind::Other });
+    let word = |token: Option<Result<Token, Error>>| match token {
+        Some(Ok(Token::Str(pos))) => Ok(Some(source[pos.to_range()].to_string())),
+        Some(Err(err)) => Err(err),
+        _ => Ok(None)
+    };
+    let at = start + (source[start as usize ..].len() - source[start as usize ..].trim_start().len()) as u32;
+    if word(tokens.next())?.is_none_or(|def| classify_identifier(&def) != IdentKind::Keyword(Keyword::Def)) {
+        return error("Expected 'def' before ';'".to_string(), at);
+    }
+    let name = match word(tokens.next())? {
+        Some(name) => name,
+        None => return error("Expected function name after 'def'".to_string(), at)
+    };
+    if !matches!(tokens.next(), Some(Ok(Token::LParen(_)))) {
+        return error(format!("Expected '(' after 'def {}'", name), at);
+    }
+    let mut params = vec![];
+    loop {
+        match tokens.next() {
+            Some(Ok(Token::RParen(_))) if params.is_empty() => break,
+            Some(Ok(Token::Str(pos))) => params.push(source[pos.to_range()].to_string()),
+            _ => return error(format!("Expected parameter name in 'def {}'", name), at)
+        }
+        match tokens.next() {
+            Some(Ok(Token::Comma(_))) => continue,
+            Some(Ok(Token::RParen(_))) => break,
+            _ => return error(format!("Expected ',' or ')' in 'def {}'", name), at)
+        }
+    }
+    match tokens.next() {
+        Some(Ok(token)) if token.is_assignment(source) => {}
+        _ => return error(format!("Expected '=' after 'def {}(...)'", name), at)
+    }
+    let body = parser::parse(&mut tokens)?;
+    Ok((name, Def { params, body }))
+}
+
+struct Calls<'p> {
+    program: &'p Program,
+    depth: u32
+}
+
+impl Functions for Calls<'_> {
+    fn contains(&self, name: &str) -> bool {
+        self.program.defs.contains_key(name)
+    }
+
+    fn call(&self, name: &str, at: u32, args: &[f32]) -> Result<f32, Error> {
+        let def = &self.program.defs[name];
+        let error = |error: String| Err(Error { error, at, kind: ErrorKind::Other });
+        if args.len() != def.params.len() {
+            return error(format!("Expected {} arguments into '{}' function", def.params.len(), name));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return error(format!("Calls to '{}' nested deeper than {}", name, MAX_CALL_DEPTH));
+        }
+        let resolve = |var: &str| def.params.iter().position(|param| param == var).map(|ix| args[ix]);
+        let calls = Calls { program: self.program, depth: self.depth + 1 };
+        eval_expr(&def.body, &Context::new(&self.program.source, &resolve).with_functions(&calls))
+    }
+}
+
+
+#[cfg(test)]
+mod program_should {
+    use super::*;
+
+    fn eval(source: &str) -> Result<f32, Error> {
+        Program::parse(source)?.eval()
+    }
+
+    #[test]
+    fn call_definitions() {
+        assert_eq!(eval("def sq(x) = x*x; sq(4)").unwrap(), 16.0);
+        assert_eq!(eval("def sq(x) = x * x; def hyp(a, b) = sq(a) + sq(b); hyp(3, 4) + 1").unwrap(), 26.0);
+        assert_eq!(eval("def fact(n) = if(n > 1, n * fact(n - 1), 1); fact(5)").unwrap(), 120.0);
+        assert_eq!(eval("def two() = 2; two() * pi()").unwrap(), 2.0 * std::f32::consts::PI);
+    }
+
+    #[test]
+    fn error_on_runaway_recursion() {
+        let error = eval("def f(x) = f(x + 1); f(0)").unwrap_err();
+        assert_eq!(error.error, "Calls to 'f' nested deeper than 64");
+    }
+
+    #[test]
+    fn error_on_invalid_definitions() {
+        assert_matches!(eval("sq(x) = x * x; sq(4)"), Err(..));
+        assert_matches!(eval("def sq x = x * x; sq(4)"), Err(..));
+        assert_matches!(eval("def f(x) == x; f(1)"), Err(..));
+        assert_matches!(eval("def sq(x) = x * x; def sq(y) = y; sq(4)"), Err(..));
+        assert_matches!(eval("def sq(x) = x * x;"), Err(..));
+        assert_matches!(eval("def sq(x) = x * y; sq(1)"), Err(..));
+        assert_matches!(eval("def sq(x) = x * x; sq(1, 2)"), Err(..));
+    }
+
+    #[test]
+    fn report_positions_in_whole_source() {
+        let error = eval("def f(x) = x;  f(1) + g").unwrap_err();
+        assert_eq!(error.at, 22);
+    }
+}
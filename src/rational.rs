@@ -0,0 +1,128 @@
+use super::{Error, ErrorKind, tokenizer, parser, numeric};
+use numeric::Numeric;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+
+// Arbitrary precision, so intermediate results never overflow; only the
+// final result has to fit into i128.
+impl Numeric for BigRational {
+    // decimal literals are exact fractions, `0.1` is 1/10
+    fn literal(text: &str) -> Result<BigRational, String> {
+        if let Some(e) = text.find(['e', 'E']) {
+            let exponent = text[e + 1..].parse::<i32>().map_err(|_| format!("Invalid number '{}'", text))?;
+            let scale = BigRational::from_integer(num_traits::pow(BigInt::from(10), exponent.unsigned_abs() as usize));
+            let mantissa = BigRational::literal(&text[..e])?;
+            return Ok(if exponent < 0 { mantissa / scale } else { mantissa * scale });
+        }
+        let (integer, fraction) = match text.find('.') {
+            Some(dot) => (&text[..dot], &text[dot + 1..]),
+            None => (text, "")
+        };
+        let numer = format!("{}{}", integer, fraction).parse::<BigInt>()
+            .map_err(|_| format!("Invalid number '{}'", text))?;
+        let denom = num_traits::pow(BigInt::from(10), fraction.len());
+        Ok(BigRational::new(numer, denom))
+    }
+
+    fn variable(_name: &str) -> Option<BigRational> {
+        None
+    }
+
+    fn from_bool(value: bool) -> BigRational {
+        BigRational::from_integer(BigInt::from(value as u8))
+    }
+
+    fn truthy(&self) -> Result<bool, String> {
+        Ok(self.is_positive())
+    }
+
+    fn neg(self) -> BigRational {
+        -self
+    }
+
+    fn add(self, rhs: BigRational) -> Result<BigRational, String> {
+        Ok(self + rhs)
+    }
+
+    fn sub(self, rhs: BigRational) -> Result<BigRational, String> {
+        Ok(self - rhs)
+    }
+
+    fn mul(self, rhs: BigRational) -> Result<BigRational, String> {
+        Ok(self * rhs)
+    }
+
+    fn div(self, rhs: BigRational) -> Result<BigRational, String> {
+        if rhs.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+        Ok(self / rhs)
+    }
+
+    fn compare(&self, rhs: &BigRational) -> Result<Option<Ordering>, String> {
+        Ok(Some(self.cmp(rhs)))
+    }
+
+    // transcendental functions have no exact rational result
+    fn call(name: &str, args: &[BigRational]) -> Option<Result<BigRational, String>> {
+        match (name, args) {
+            ("dist", [a, b]) => Some(Ok((a - b).abs())),
+            ("pi", _) | ("e", _) | ("tau", _) | ("sin_norm", _) | ("cos_norm", _) | ("tan_norm", _) =>
+                Some(Err(format!("Function '{}' has no exact rational result", name))),
+            _ => None
+        }
+    }
+}
+
+/// Evaluates the expression with exact rational arithmetic, returning the
+/// reduced `(numerator, denominator)` of the result; the denominator is positive.
+pub fn evaluate_rational(expression: &str) -> Result<(i128, i128), Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    let value: BigRational = numeric::eval(&expr, expression)?;
+    match (value.numer().to_i128(), value.denom().to_i128()) {
+        (Some(numer), Some(denom)) => Ok((numer, denom)),
+        _ => Err(Error {
+            error: format!("Result {} does not fit into i128", value),
+            at: 0,
+            kind: ErrorKind::Other
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod rational_should {
+    use super::*;
+
+    #[test]
+    fn add_fractions_exactly() {
+        assert_eq!(evaluate_rational("1/3 + 1/3 + 1/3").unwrap(), (1, 1));
+        assert_eq!(evaluate_rational("0.1 + 0.2").unwrap(), (3, 10));
+        assert_eq!(evaluate_rational("1/3 - 1/2").unwrap(), (-1, 6));
+        assert_eq!(evaluate_rational("(2/3) / (4/9)").unwrap(), (3, 2));
+        assert_eq!(evaluate_rational("1.5e2 + 2e-1").unwrap(), (751, 5));
+    }
+
+    #[test]
+    fn compare_exactly() {
+        assert_eq!(evaluate_rational("0.1 * 3 = 0.3").unwrap(), (1, 1));
+        assert_eq!(evaluate_rational("if(1/3 < 0.3333, 1, 2)").unwrap(), (2, 1));
+    }
+
+    #[test]
+    fn not_overflow_on_intermediate_results() {
+        let huge = "100000000000000000000000000000000000000000";
+        let expression = format!("{} * {} / ({} * {})", huge, huge, huge, huge);
+        assert_eq!(evaluate_rational(&expression).unwrap(), (1, 1));
+        assert_matches!(evaluate_rational(&format!("{} * {}", huge, huge)), Err(..));
+    }
+
+    #[test]
+    fn error_on_division_by_zero_and_transcendentals() {
+        assert_eq!(evaluate_rational("1 / (1 - 1)").unwrap_err().at, 2);
+        assert_matches!(evaluate_rational("pi()"), Err(..));
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt::Display;
+use std::ops::{Add, Sub, Mul, Div, Rem, Neg};
+use std::str::FromStr;
+
+/// The floating point types the main evaluator computes in, so `evaluate`
+/// and `evaluate_f64` share one code path.
+pub(crate) trait Real: Copy + PartialOrd + Display + FromStr
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+    + Rem<Output = Self> + Neg<Output = Self> {
+    const ZERO: Self;
+    const ONE: Self;
+    const HUNDRED: Self;
+    const PI: Self;
+    const E: Self;
+    const TAU: Self;
+    const INFINITY: Self;
+    const NEG_INFINITY: Self;
+
+    // 1 or 0, the way comparisons and logical operators report their result
+    fn from_bool(value: bool) -> Self {
+        if value {Self::ONE} else {Self::ZERO}
+    }
+
+    fn to_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+    fn abs(self) -> Self;
+    fn floor(self) -> Self;
+    fn ceil(self) -> Self;
+    fn round(self) -> Self;
+    fn trunc(self) -> Self;
+    fn fract(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn ln(self) -> Self;
+    fn asin(self) -> Self;
+    fn log10(self) -> Self;
+    fn log(self, base: Self) -> Self;
+    fn exp(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn tan(self) -> Self;
+    fn powf(self, exponent: Self) -> Self;
+    fn rem_euclid(self, rhs: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn is_finite(self) -> bool;
+    fn is_infinite(self) -> bool;
+    fn is_nan(self) -> bool;
+}
+
+macro_rules! real {
+    ($float:ident) => {
+        impl Real for $float {
+            const ZERO: $float = 0.0;
+            const ONE: $float = 1.0;
+            const HUNDRED: $float = 100.0;
+            const PI: $float = std::$float::consts::PI;
+            const E: $float = std::$float::consts::E;
+            const TAU: $float = std::$float::consts::TAU;
+            const INFINITY: $float = $float::INFINITY;
+            const NEG_INFINITY: $float = $float::NEG_INFINITY;
+
+            fn to_f64(self) -> f64 { self as f64 }
+            fn from_f64(value: f64) -> $float { value as $float }
+            fn abs(self) -> $float { $float::abs(self) }
+            fn floor(self) -> $float { $float::floor(self) }
+            fn ceil(self) -> $float { $float::ceil(self) }
+            fn round(self) -> $float { $float::round(self) }
+            fn trunc(self) -> $float { $float::trunc(self) }
+            fn fract(self) -> $float { $float::fract(self) }
+            fn sqrt(self) -> $float { $float::sqrt(self) }
+            fn ln(self) -> $float { $float::ln(self) }
+            fn asin(self) -> $float { $float::asin(self) }
+            fn log10(self) -> $float { $float::log10(self) }
+            fn log(self, base: $float) -> $float { $float::log(self, base) }
+            fn exp(self) -> $float { $float::exp(self) }
+            fn sin(self) -> $float { $float::sin(self) }
+            fn cos(self) -> $float { $float::cos(self) }
+            fn tan(self) -> $float { $float::tan(self) }
+            fn powf(self, exponent: $float) -> $float { $float::powf(self, exponent) }
+            fn rem_euclid(self, rhs: $float) -> $float { $float::rem_euclid(self, rhs) }
+            fn min(self, other: $float) -> $float { $float::min(self, other) }
+            fn max(self, other: $float) -> $float { $float::max(self, other) }
+            fn is_finite(self) -> bool { $float::is_finite(self) }
+            fn is_infinite(self) -> bool { $float::is_infinite(self) }
+            fn is_nan(self) -> bool { $float::is_nan(self) }
+        }
+    };
+}
+
+real!(f32);
+real!(f64);
@@ -0,0 +1,80 @@
+use super::{Expr, Error, Context};
+
+/// A parsed expression holding its numbers and names instead of positions,
+/// so it can be kept, sent to another thread or serialized without the source.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResolvedExpr {
+    Number(f32),
+    Variable(String),
+    Func { name: String, params: Vec<ResolvedExpr> },
+    Unary { operator_ix: u8, expr: Box<ResolvedExpr> },
+    Binary { left: Box<ResolvedExpr>, operator_ix: u8, right: Box<ResolvedExpr> },
+    Index { base: String, index: Box<ResolvedExpr> },
+    Ternary { condition: Box<ResolvedExpr>, then: Box<ResolvedExpr>, otherwise: Box<ResolvedExpr> }
+}
+
+/// Copies the numbers and names `expr` refers to out of `source`, the string
+/// it was parsed from. Fails only on a hand-built tree pointing at an invalid number.
+pub fn resolve(expr: &Expr, source: &str) -> Result<ResolvedExpr, Error> {
+    resolve_in(expr, &Context::new(source, &|_| None))
+}
+
+fn resolve_in(expr: &Expr, ctx: &Context) -> Result<ResolvedExpr, Error> {
+    let boxed = |expr| resolve_in(expr, ctx).map(Box::new);
+    Ok(match expr {
+        Expr::Number(pos) => ResolvedExpr::Number(ctx.number(*pos)?),
+        Expr::Variable(pos) => ResolvedExpr::Variable(ctx.source[pos.to_range()].to_string()),
+        Expr::Func(func) => ResolvedExpr::Func {
+            name: ctx.source[func.name.to_range()].to_string(),
+            params: func.params.iter().map(|param| resolve_in(param, ctx)).collect::<Result<_, _>>()?
+        },
+        Expr::Unary { expr, operator_ix, .. } => ResolvedExpr::Unary { operator_ix: *operator_ix, expr: boxed(expr)? },
+        Expr::Binary(bin) => ResolvedExpr::Binary { left: boxed(&bin.left)?, operator_ix: bin.operator_ix, right: boxed(&bin.right)? },
+        Expr::Index(element) => ResolvedExpr::Index {
+            base: ctx.source[element.base.to_range()].to_string(),
+            index: boxed(&element.index)?
+        },
+        Expr::Ternary(ternary) => ResolvedExpr::Ternary {
+            condition: boxed(&ternary.condition)?,
+            then: boxed(&ternary.then)?,
+            otherwise: boxed(&ternary.otherwise)?
+        }
+    })
+}
+
+
+#[cfg(test)]
+mod resolve_should {
+    use super::*;
+    use crate::{parse, operator_symbol};
+
+    #[test]
+    fn copy_numbers_and_names() {
+        let resolved = {
+            let source = String::from("max(x, 1_000) * -v[50%]");
+            resolve(parse(&source).unwrap().expr(), &source).unwrap()
+        };
+        let (left, right) = match &resolved {
+            ResolvedExpr::Binary { left, operator_ix, right } if operator_symbol(*operator_ix).unwrap() == "*" => (left, right),
+            _ => panic!()
+        };
+        assert_eq!(**left, ResolvedExpr::Func {
+            name: "max".to_string(),
+            params: vec![ResolvedExpr::Variable("x".to_string()), ResolvedExpr::Number(1000.0)]
+        });
+        assert_matches!(&**right, ResolvedExpr::Unary { expr, .. }
+            if **expr == ResolvedExpr::Index { base: "v".to_string(), index: Box::new(ResolvedExpr::Number(0.5)) });
+    }
+
+    #[test]
+    fn resolve_ternary() {
+        let source = "a ? 1 : 2";
+        let resolved = resolve(parse(source).unwrap().expr(), source).unwrap();
+        assert_eq!(resolved, ResolvedExpr::Ternary {
+            condition: Box::new(ResolvedExpr::Variable("a".to_string())),
+            then: Box::new(ResolvedExpr::Number(1.0)),
+            otherwise: Box::new(ResolvedExpr::Number(2.0))
+        });
+    }
+}
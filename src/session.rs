@@ -0,0 +1,98 @@
+use super::{Error, ErrorKind, Context, IdentKind, Keyword, tokenizer, parser, eval_expr, classify_identifier};
+use tokenizer::Token;
+use std::collections::HashMap;
+
+/// Evaluates a series of expressions that share state, like a calculator
+/// with memory. `ans` holds the previous result (0 initially) and
+/// `let name = expression` stores a variable for later evaluations.
+#[derive(Debug, Default)]
+pub struct Session {
+    vars: HashMap<String, f32>,
+    ans: f32
+}
+
+impl Session {
+
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    pub fn eval(&mut self, expression: &str) -> Result<f32, Error> {
+        let mut tokens = tokenizer::Tokens::new(expression).peekable();
+        let target = assignment_target(&mut tokens, expression)?;
+        let expr = parser::parse(&mut tokens)?;
+        let vars = &self.vars;
+        let ans = self.ans;
+        let resolve = |name: &str| if name == "ans" { Some(ans) } else { vars.get(name).copied() };
+        let value = eval_expr(&expr, &Context::new(expression, &resolve))?;
+        if let Some(name) = target {
+            self.vars.insert(name, value);
+        }
+        self.ans = value;
+        Ok(value)
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        if name == "ans" { Some(self.ans) } else { self.vars.get(name).copied() }
+    }
+}
+
+// consumes a leading `let name =` and returns the name
+fn assignment_target(tokens: &mut std::iter::Peekable<tokenizer::Tokens>, source: &str) -> Result<Option<String>, Error> {
+    let at = match tokens.peek() {
+        Some(Ok(Token::Str(pos))) if classify_identifier(&source[pos.to_range()]) == IdentKind::Keyword(Keyword::Let) => pos.at,
+        _ => return Ok(None)
+    };
+    tokens.next();
+    let name = match tokens.next() {
+        Some(Ok(Token::Str(pos))) => source[pos.to_range()].to_string(),
+        Some(Err(err)) => return Err(err),
+        _ => return Err(Error {
+            error: "Expected variable name after 'let'".to_string(),
+            at,
+            kind: ErrorKind::Other
+        })
+    };
+    match tokens.next() {
+        Some(Ok(token)) if token.is_assignment(source) => Ok(Some(name)),
+        Some(Err(err)) => Err(err),
+        _ => Err(Error {
+            error: format!("Expected '=' after 'let {}'", name),
+            at,
+            kind: ErrorKind::Other
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod session_should {
+    use super::*;
+
+    #[test]
+    fn remember_previous_result() {
+        let mut session = Session::new();
+        assert_eq!(session.eval("ans").unwrap(), 0.0);
+        assert_eq!(session.eval("1+1").unwrap(), 2.0);
+        assert_eq!(session.eval("ans * 2").unwrap(), 4.0);
+    }
+
+    #[test]
+    fn persist_assigned_variables() {
+        let mut session = Session::new();
+        assert_eq!(session.eval("let rate = 0.5").unwrap(), 0.5);
+        assert_eq!(session.eval("let total = 10 * rate").unwrap(), 5.0);
+        assert_eq!(session.eval("total + ans + rate").unwrap(), 10.5);
+        assert_eq!(session.get("total"), Some(5.0));
+    }
+
+    #[test]
+    fn error_on_unknown_variable_or_bad_assignment() {
+        let mut session = Session::new();
+        assert_matches!(session.eval("missing + 1"), Err(..));
+        assert_matches!(session.eval("let = 1"), Err(..));
+        assert_matches!(session.eval("let x 1"), Err(..));
+        assert_matches!(session.eval("let x == 3"), Err(..));
+        assert_eq!(session.get("x"), None);
+    }
+}
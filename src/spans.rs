@@ -0,0 +1,151 @@
+use super::{Error, Position, tokenizer, parser};
+
+#[derive(PartialEq)]
+pub(crate) enum Kind<'a> {
+    Number(&'a str),
+    Variable(&'a str),
+    Func(&'a str),
+    Index(&'a str),
+    Ternary,
+    Unary(u8),
+    Binary(u8)
+}
+
+// a node with the span of its whole subtree, parentheses included
+pub(crate) struct Tree<'a> {
+    pub kind: Kind<'a>,
+    pub span: Position,
+    pub children: Vec<Tree<'a>>
+}
+
+pub(crate) struct TreeBuilder<'a> {
+    source: &'a str
+}
+
+impl<'a> TreeBuilder<'a> {
+    pub fn new(source: &'a str) -> TreeBuilder<'a> {
+        TreeBuilder { source }
+    }
+}
+
+fn span(start: u32, end: u32) -> Position {
+    Position { at: start, len: (end - start) as u16 }
+}
+
+fn end(pos: Position) -> u32 {
+    pos.at + pos.len as u32
+}
+
+impl<'a> parser::Builder for TreeBuilder<'a> {
+    type Node = Tree<'a>;
+
+    fn number(&mut self, pos: Position) -> Tree<'a> {
+        Tree { kind: Kind::Number(&self.source[pos.to_range()]), span: pos, children: vec![] }
+    }
+
+    fn variable(&mut self, pos: Position) -> Tree<'a> {
+        Tree { kind: Kind::Variable(&self.source[pos.to_range()]), span: pos, children: vec![] }
+    }
+
+    fn func(&mut self, name: Position, params: Vec<Tree<'a>>) -> Tree<'a> {
+        // the parser has checked that the closing parenthesis is the next one
+        let last = params.last().map_or(end(name), |param| end(param.span));
+        let close = last + self.source[last as usize ..].find(')').unwrap() as u32;
+        Tree { kind: Kind::Func(&self.source[name.to_range()]), span: span(name.at, close + 1), children: params }
+    }
+
+    fn ternary(&mut self, condition: Tree<'a>, _at: u32, then: Tree<'a>, otherwise: Tree<'a>) -> Tree<'a> {
+        let span = span(condition.span.at, end(otherwise.span));
+        Tree { kind: Kind::Ternary, span, children: vec![condition, then, otherwise] }
+    }
+
+    fn index(&mut self, base: Position, index: Tree<'a>) -> Tree<'a> {
+        // the parser has checked that the closing bracket is the next one
+        let close = end(index.span) + self.source[end(index.span) as usize ..].find(']').unwrap() as u32;
+        Tree { kind: Kind::Index(&self.source[base.to_range()]), span: span(base.at, close + 1), children: vec![index] }
+    }
+
+    fn unary(&mut self, at: u32, operator_ix: u8, expr: Tree<'a>) -> Tree<'a> {
+        Tree { kind: Kind::Unary(operator_ix), span: span(at, end(expr.span)), children: vec![expr] }
+    }
+
+    fn binary(&mut self, left: Tree<'a>, _at: u32, operator_ix: u8, right: Tree<'a>) -> Tree<'a> {
+        let span = span(left.span.at, end(right.span));
+        Tree { kind: Kind::Binary(operator_ix), span, children: vec![left, right] }
+    }
+
+    fn group(&mut self, open: u32, close: u32, expr: Tree<'a>) -> Tree<'a> {
+        Tree { span: span(open, close + 1), ..expr }
+    }
+}
+
+/// The kind of an expression node.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub enum NodeKind {
+    Number,
+    Variable,
+    Func,
+    Index,
+    Ternary,
+    Unary,
+    Binary,
+}
+
+/// A node and the span of its whole subtree, including parentheses around it.
+#[derive(Debug, PartialEq)]
+pub struct NodeInfo {
+    pub kind: NodeKind,
+    pub span: Position,
+}
+
+/// Finds the innermost node whose span contains the byte `offset`, e.g. the
+/// node under an editor's cursor. Returns `None` for offsets outside the expression.
+pub fn node_at(expression: &str, offset: usize) -> Result<Option<NodeInfo>, Error> {
+    let tree = parser::parse_with(&mut tokenizer::Tokens::new(expression), &mut TreeBuilder::new(expression))?;
+    let contains = |tree: &Tree| tree.span.to_range().contains(&offset);
+    if !contains(&tree) {
+        return Ok(None);
+    }
+    let mut node = &tree;
+    while let Some(child) = node.children.iter().find(|child| contains(child)) {
+        node = child;
+    }
+    let kind = match node.kind {
+        Kind::Number(_) => NodeKind::Number,
+        Kind::Variable(_) => NodeKind::Variable,
+        Kind::Func(_) => NodeKind::Func,
+        Kind::Index(_) => NodeKind::Index,
+        Kind::Ternary => NodeKind::Ternary,
+        Kind::Unary(_) => NodeKind::Unary,
+        Kind::Binary(_) => NodeKind::Binary
+    };
+    Ok(Some(NodeInfo { kind, span: node.span }))
+}
+
+
+#[cfg(test)]
+mod spans_should {
+    use super::*;
+
+    fn node(expression: &str, offset: usize) -> Option<(NodeKind, u32, u16)> {
+        node_at(expression, offset).unwrap().map(|info| (info.kind, info.span.at, info.span.len))
+    }
+
+    #[test]
+    fn find_innermost_node() {
+        assert_eq!(node("1 + 2 * 3", 4), Some((NodeKind::Number, 4, 1)));
+        assert_eq!(node("1 + 2 * 3", 6), Some((NodeKind::Binary, 4, 5)));
+        assert_eq!(node("1 + 2 * 3", 2), Some((NodeKind::Binary, 0, 9)));
+        assert_eq!(node("max(a, (b))", 7), Some((NodeKind::Variable, 7, 3)));
+        assert_eq!(node("max(a, (b))", 10), Some((NodeKind::Func, 0, 11)));
+        assert_eq!(node("v[i + 1]", 7), Some((NodeKind::Index, 0, 8)));
+    }
+
+    #[test]
+    fn find_nothing_outside_expression() {
+        assert_eq!(node(" 1 + 2 ", 0), None);
+        assert_eq!(node("1 + 2", 5), None);
+        assert_matches!(node_at("1 +", 0), Err(..));
+    }
+}
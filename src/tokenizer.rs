@@ -1,5 +1,6 @@
-use super::{Error, Position, operator};
+use super::{Error, ErrorKind, Position, ParseOptions, IdentKind, operator, classify_identifier, hex};
 
+/// A token produced by `Tokens`, with byte offsets into the source.
 #[derive(Debug, PartialEq)]
 #[derive(Clone, Copy)]
 pub enum Token {
@@ -9,22 +10,172 @@ pub enum Token {
     Comma  (u32),
     LParen (u32),
     RParen (u32),
+    LBracket (u32),
+    RBracket (u32),
+    Question (u32),
+    Colon (u32),
+    Newline (u32), // only with newline separators, see `Tokens::with_newline_separators`
 }
 
+impl Token {
+    /// Byte offset where the token starts.
+    pub fn at(&self) -> u32 {
+        match *self {
+            Token::Number(pos) | Token::Str(pos) => pos.at,
+            Token::Operator { at, .. } => at,
+            Token::Comma(at) | Token::LParen(at) | Token::RParen(at) | Token::LBracket(at)
+                | Token::RBracket(at) | Token::Question(at) | Token::Colon(at) | Token::Newline(at) => at
+        }
+    }
+
+    // a single `=`, not the `==` that the default aliases also turn into `=`
+    pub(crate) fn is_assignment(&self, source: &str) -> bool {
+        match *self {
+            Token::Operator { at, operator_ix } => Some(operator_ix) == operator::find("=")
+                && source[at as usize ..].starts_with('=') && !source[at as usize + 1 ..].starts_with('='),
+            _ => false
+        }
+    }
+
+    /// The part of the source the token covers. `None` for an operator, which
+    /// may be spelled as an alias of a different length; `tokenize_with_spans`
+    /// measures those in the source.
+    pub fn position(&self) -> Option<Position> {
+        match *self {
+            Token::Number(pos) | Token::Str(pos) => Some(pos),
+            Token::Comma(at) | Token::LParen(at) | Token::RParen(at) | Token::LBracket(at)
+                | Token::RBracket(at) | Token::Question(at) | Token::Colon(at) | Token::Newline(at) => Some(Position { at, len: 1 }),
+            Token::Operator { .. } => None
+        }
+    }
+}
+
+/// Splits an expression into tokens, e.g. for highlighting or linting:
+/// `for token in Tokens::new("1 + x") { ... }`.
 pub struct Tokens<'a> {
+    source: &'a str,
     chars: std::iter::Peekable<std::str::Chars<'a>>,
-    byte_ix: u32
+    byte_ix: u32,
+    prev: Option<Token>,
+    options: ParseOptions,
+    newline_separators: bool,
+    paren_depth: u32,
+    #[cfg(feature = "units")]
+    unit_literals: bool,
+    #[cfg(feature = "complex")]
+    imaginary_literals: bool
 }
 
 impl Tokens<'_> {
 
-    pub fn new(val:&str) -> Tokens {
+    pub fn new(val:&str) -> Tokens<'_> {
+        Tokens::with_options(val, ParseOptions::default())
+    }
+
+    pub fn with_options(val:&str, options: ParseOptions) -> Tokens<'_> {
         Tokens {
+            source: val,
             chars: val.chars().peekable(),
             byte_ix:0,
+            prev: None,
+            options,
+            newline_separators: false,
+            paren_depth: 0,
+            #[cfg(feature = "units")]
+            unit_literals: false,
+            #[cfg(feature = "complex")]
+            imaginary_literals: false
         }
     }
 
+    // byte offset of the next character to read
+    pub(crate) fn offset(&self) -> u32 {
+        self.byte_ix
+    }
+
+    // tokens of a part of a larger source keep their positions in the whole source
+    pub fn skip_to(mut self, at: u32) -> Self {
+        while self.byte_ix < at {
+            self.next_char();
+        }
+        self
+    }
+
+    // a newline outside parentheses ends an expression, so each line of a file can hold one;
+    // blank lines and lines continued inside parentheses do not produce separators
+    pub fn with_newline_separators(mut self) -> Self {
+        self.newline_separators = true;
+        self
+    }
+
+    // numbers absorb a following unit name, so `3 m` becomes a single number token
+    #[cfg(feature = "units")]
+    pub fn with_unit_literals(mut self) -> Self {
+        self.unit_literals = true;
+        self
+    }
+
+    #[cfg(feature = "units")]
+    fn unit_suffix(&mut self, number: Position) -> Position {
+        let rest = &self.source[self.byte_ix as usize ..];
+        let unit_start = rest.len() - rest.trim_start_matches(|ch: char| ch.is_ascii_whitespace()).len();
+        let unit_len = rest[unit_start ..].find(|ch: char| !ch.is_alphabetic()).unwrap_or(rest.len() - unit_start);
+        let unit = &rest[unit_start .. unit_start + unit_len];
+        // a unit name followed by '(' is a function call instead
+        if !crate::units::is_unit(unit) || rest[unit_start + unit_len ..].starts_with('(') {
+            return number;
+        }
+        let end = self.byte_ix + (unit_start + unit_len) as u32;
+        while self.byte_ix < end {
+            self.next_char();
+        }
+        Position { at: number.at, len: (end - number.at) as u16 }
+    }
+
+    // numbers absorb a directly following `i`, so `4i` is a single number token
+    #[cfg(feature = "complex")]
+    pub fn with_imaginary_literals(mut self) -> Self {
+        self.imaginary_literals = true;
+        self
+    }
+
+    #[cfg(feature = "complex")]
+    fn imaginary_suffix(&mut self, number: Position) -> Position {
+        let mut rest = self.source[self.byte_ix as usize ..].chars();
+        let is_suffix = rest.next() == Some('i')
+            && rest.next().is_none_or(|ch| !(self.options.identifier_chars.is_continue)(ch));
+        if !is_suffix {
+            return number;
+        }
+        self.next_char();
+        Position { at: number.at, len: number.len + 1 }
+    }
+
+    // `%` touching the end of a number is a percent sign, so `50%` is a single number
+    // token worth 0.5, unless an operand follows it: `10%3` is a remainder like `10 % 3`.
+    // A `%` with whitespace before it is always the remainder operator.
+    //   `50%`, `10% + 5`, `(10%)`  percent
+    //   `10 % 3`, `10%3`, `10% 3`  remainder
+    fn percent_suffix(&mut self, number: Position) -> Position {
+        let after = match self.source[self.byte_ix as usize ..].strip_prefix('%') {
+            Some(after) => after.trim_start(),
+            None => return number
+        };
+        let is_continue = self.options.identifier_chars.is_continue;
+        let word_len = after.find(|ch: char| !is_continue(ch)).unwrap_or(after.len());
+        let operand_follows = match after.chars().next() {
+            Some(ch) if ch.is_ascii_digit() || ch == '.' || ch == '(' => true,
+            // keyword operators like `xor` are not operands
+            Some(ch) if (self.options.identifier_chars.is_start)(ch) => operator::is_keyword(&after[..word_len]).is_none(),
+            _ => false
+        };
+        if operand_follows {
+            return number;
+        }
+        self.next_char();
+        Position { at: number.at, len: number.len + 1 }
+    }
+
     fn next_char(&mut self) -> Option<(u32, char)> {
         if let Some(ch) = self.chars.next() {
             let prev_ix = self.byte_ix;
@@ -34,32 +185,73 @@ impl Tokens<'_> {
         None
     }
 
-    fn number(&mut self, at:u32) -> Position {
+    fn number(&mut self, at:u32) -> Result<Position, Error> {
+        if &self.source[at as usize .. self.byte_ix as usize] == "0" && matches!(self.chars.peek(), Some('x' | 'X' | 'b' | 'B')) {
+            return self.prefixed_number(at);
+        }
         let mut len = 1;
         while let Some(ch) = self.chars.peek() {
-            if ch.is_ascii_digit() || *ch == '.' {
+            if ch.is_ascii_digit() || *ch == '.' || *ch == '_' {
                 len += 1;
                 let _ = self.next_char();
             } else {
                 break;
             }
         }    
-        Position { at, len }
+        let text = &self.source[at as usize .. self.byte_ix as usize];
+        // underscores separate digits, as in `1_000`
+        let misplaced_separator = text.split('_').skip(1).any(|after| !after.starts_with(|ch: char| ch.is_ascii_digit()))
+            || text.split('_').rev().skip(1).any(|before| !before.ends_with(|ch: char| ch.is_ascii_digit()));
+        if text.matches('.').count() > 1 || misplaced_separator {
+            return Err(Error {
+                error: format!("Malformed number '{}'", text),
+                at,
+                kind: ErrorKind::InvalidNumber
+            });
+        }
+        // `1e3` or `2.5E-4`; an `e` without digits after it starts an identifier instead, as in `1e` or `1ex`
+        let has_digits = text.contains(|ch: char| ch.is_ascii_digit());
+        let exponent = exponent_len(&self.source[self.byte_ix as usize ..]);
+        if has_digits && exponent > 0 {
+            for _ in 0..exponent {
+                let _ = self.next_char();
+            }
+            len += exponent as u16;
+        }
+        Ok(Position { at, len })
     }
     
+    // `0xFF`, `0b1010` or `0x1.8p3`, where a sign may only follow the `p`
+    fn prefixed_number(&mut self, at:u32) -> Result<Position, Error> {
+        while let Some(&ch) = self.chars.peek() {
+            let after_p = self.source[.. self.byte_ix as usize].ends_with(['p', 'P']);
+            if ch.is_ascii_alphanumeric() || ch == '.' || (after_p && (ch == '+' || ch == '-')) {
+                let _ = self.next_char();
+            } else {
+                break;
+            }
+        }
+        let text = &self.source[at as usize .. self.byte_ix as usize];
+        if hex::parse_prefixed(text).is_none() {
+            return Err(Error {
+                error: format!("Malformed number '{}'", text),
+                at,
+                kind: ErrorKind::InvalidNumber
+            });
+        }
+        Ok(Position { at, len: text.len() as u16 })
+    }
+
     fn string(&mut self, at:u32) -> Token {
-        let mut len = 1;
         while let Some(ch) = self.chars.peek() {
-            // strings can have digits in them
-            if ch.is_alphanumeric() || *ch == '_' {
-                len += 1;
+            if (self.options.identifier_chars.is_continue)(*ch) {
                 let _ = self.next_char();
             } else {
                 break;
             }
         }
     
-        Token::Str(Position { at, len })
+        Token::Str(Position { at, len: (self.byte_ix - at) as u16 })
     }
 }
 
@@ -67,33 +259,98 @@ impl Iterator for Tokens<'_> {
     type Item = Result<Token, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut char_num = 0;
+        let token = self.token();
+        if let Some(Ok(token)) = token {
+            match token {
+                Token::LParen(_) | Token::LBracket(_) => self.paren_depth += 1,
+                Token::RParen(_) | Token::RBracket(_) => self.paren_depth = self.paren_depth.saturating_sub(1),
+                _ => {}
+            }
+            self.prev = Some(token);
+        }
+        token
+    }
+}
+
+/// Tokenizes the whole expression, pairing each token with the full span
+/// it covers in the source, e.g. both characters of `>=`.
+pub fn tokenize_with_spans(expression: &str) -> Result<Vec<(Token, Position)>, Error> {
+    let mut tokens = Tokens::new(expression);
+    let mut spans = vec![];
+    while let Some(token) = tokens.next() {
+        let token = token?;
+        // measured in the source, an alias like `×` is longer than the operator it stands for
+        let position = token.position().unwrap_or(Position { at: token.at(), len: (tokens.byte_ix - token.at()) as u16 });
+        spans.push((token, position));
+    }
+    Ok(spans)
+}
+
+impl Tokens<'_> {
+
+    fn token(&mut self) -> Option<Result<Token, Error>> {
+        let mut space_before = false;
 
         while let Some((byte_ix, ch)) = self.next_char() {
-            char_num += 1;
-            if ch.is_ascii_whitespace() {
+            if ch == '\n' && self.newline_separators && self.paren_depth == 0
+                && !matches!(self.prev, None | Some(Token::Newline(..))) {
+                return Some(Ok(Token::Newline(byte_ix)));
+            } else if ch.is_ascii_whitespace() {
+                space_before = true;
                 continue;
             } else if ch.is_ascii_digit() || ch == '.' {
-                let position = self.number(byte_ix);
+                let position = match self.number(byte_ix) {
+                    Ok(position) => position,
+                    Err(err) => return Some(Err(err))
+                };
+                #[cfg(feature = "units")]
+                let position = if self.unit_literals { self.unit_suffix(position) } else { position };
+                #[cfg(feature = "complex")]
+                let position = if self.imaginary_literals { self.imaginary_suffix(position) } else { position };
                 if position.len == 1 && ch == '.' {
                     return Some(Err(Error{
-                        error: format!("Unexpected token '.' at position {}", char_num),
-                        at: char_num
+                        error: "Unexpected token '.'".to_string(),
+                        at: byte_ix,
+                        kind: ErrorKind::UnexpectedToken
+                    }));
+                }
+                return Some(Ok(Token::Number(self.percent_suffix(position))));
+            } else if self.starts_identifier(ch) {
+                let token = self.string(byte_ix);
+                let word = &self.source[byte_ix as usize .. self.byte_ix as usize];
+                let keyword = operator::is_keyword(word).filter(|_| self.options.aliases.canonical());
+                if let Some(operator_ix) = self.options.aliases.resolve_word(word).or(keyword) {
+                    return Some(Ok(Token::Operator { at: byte_ix, operator_ix }));
+                }
+                if self.options.reject_mixed_script_identifiers {
+                    if let Err(err) = single_script(word, byte_ix) {
+                        return Some(Err(err));
+                    }
+                }
+                if self.options.strict_parens && !self.source[self.byte_ix as usize ..].trim_start().starts_with('(')
+                    && matches!(classify_identifier(word), IdentKind::BuiltinFunction | IdentKind::Constant) {
+                    return Some(Err(Error {
+                        error: format!("Did you forget '()' after '{}'?", word),
+                        at: byte_ix,
+                        kind: ErrorKind::Other
                     }));
                 }
-                return Some(Ok(Token::Number(position)));
-            } else if let Some(ix1) = operator::is_operator(ch) {
-                let mut operator_ix = ix1;
-                // attempt to resolve multi char operators
-                if let Some(char2) = self.chars.peek() {
-                    if let Some(ix2) = operator::is_multi_char(ch, *char2) {
-                        self.next_char();
-                        operator_ix = ix2;    
+                return Some(Ok(token));
+            } else if let Some(operator_ix) = self.operator(byte_ix, ch) {
+                if self.options.require_operator_spacing && self.follows_operand() {
+                    let space_after = self.chars.peek().is_none_or(|ch| ch.is_ascii_whitespace());
+                    if !space_before || !space_after {
+                        return Some(Err(Error{
+                            error: format!("Operator '{}' must be surrounded by spaces",
+                                                                operator::from(operator_ix)),
+                            at: byte_ix,
+                            kind: ErrorKind::Other
+                        }));
                     }
-                }    
+                }
                 return Some(Ok(Token::Operator {
                     at: byte_ix,
-                    operator_ix: operator_ix,
+                    operator_ix,
                 }));
             } else if ch == ',' {
                 return Some(Ok(Token::Comma(byte_ix)));
@@ -101,26 +358,124 @@ impl Iterator for Tokens<'_> {
                 return Some(Ok(Token::LParen(byte_ix)));
             } else if ch == ')' {
                 return Some(Ok(Token::RParen(byte_ix)));
-            } else if ch.is_ascii_punctuation() && ch != '_' {
+            } else if ch == '[' {
+                return Some(Ok(Token::LBracket(byte_ix)));
+            } else if ch == ']' {
+                return Some(Ok(Token::RBracket(byte_ix)));
+            } else if ch == '?' {
+                return Some(Ok(Token::Question(byte_ix)));
+            } else if ch == ':' {
+                return Some(Ok(Token::Colon(byte_ix)));
+            } else {
                 return Some(Err(Error{
-                    error: format!("Found reserved character {} at {}",
-                                                                ch, char_num),
-                    at: char_num
+                    error: format!("Found reserved character {} at {}, expected a number, a name, one of ( ) [ ] , ? : or an operator: {}",
+                                                                ch, byte_ix, self.options.aliases.spellings().join(" ")),
+                    at: byte_ix,
+                    kind: ErrorKind::UnexpectedToken
                 }));
-            } else {
-                // this must be allowed
-                return Some(Ok(self.string(byte_ix)));
             }
         }
         None
     }
+
+    fn operator(&mut self, byte_ix: u32, ch: char) -> Option<u8> {
+        if !ch.is_alphanumeric() {
+            if let Some((alias, operator_ix)) = self.options.aliases.resolve(&self.source[byte_ix as usize ..]) {
+                // the first char is already consumed
+                let extra = alias.chars().count() - 1;
+                for _ in 0..extra {
+                    self.next_char();
+                }
+                return Some(operator_ix);
+            }
+        }
+        if !self.options.aliases.canonical() {
+            return None;
+        }
+        // attempt to resolve multi char operators
+        if let Some(char2) = self.chars.peek() {
+            if let Some(operator_ix) = operator::is_multi_char(ch, *char2) {
+                self.next_char();
+                return Some(operator_ix);
+            }
+        }    
+        operator::is_operator(ch)
+    }
+
+    // an operator right after an operand is binary, otherwise it is a prefix
+    // a symbol allowed to start identifiers, like the `-` of CSS-style `--var`, does so only
+    // where no operand comes before it and more of the identifier follows, so `a - b` and `1 -2` still subtract
+    fn starts_identifier(&mut self, ch: char) -> bool {
+        let chars = self.options.identifier_chars;
+        if !(chars.is_start)(ch) {
+            return false;
+        }
+        ch.is_alphanumeric() || ch == '_'
+            || (!self.follows_operand() && self.chars.peek().is_some_and(|next| (chars.is_continue)(*next)))
+    }
+
+    fn follows_operand(&self) -> bool {
+        matches!(self.prev, Some(Token::Number(..)) | Some(Token::Str(..)) | Some(Token::RParen(..)) | Some(Token::RBracket(..)))
+    }
 }
 
 
+// length of the `e3`, `E-4` or `e+10` that `text` starts with, 0 when it does not start with an exponent
+pub(crate) fn exponent_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    if !matches!(bytes.first(), Some(b'e' | b'E')) {
+        return 0;
+    }
+    let sign = matches!(bytes.get(1), Some(b'+' | b'-')) as usize;
+    let digits = bytes[1 + sign ..].iter().take_while(|byte| byte.is_ascii_digit()).count();
+    if digits == 0 { 0 } else { 1 + sign + digits }
+}
+
+// errors at the first letter whose script differs from the letters before it
+fn single_script(word: &str, at: u32) -> Result<(), Error> {
+    let mut first: Option<&str> = None;
+    for (ix, ch) in word.char_indices() {
+        let script = match script(ch) {
+            Some(script) => script,
+            None => continue
+        };
+        match first {
+            None => first = Some(script),
+            Some(first) if first != script => return Err(Error {
+                error: format!("Identifier '{}' mixes {} and {} scripts", word, first, script),
+                at: at + ix as u32,
+                kind: ErrorKind::Other
+            }),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// None for characters shared between scripts, like digits and '_'
+fn script(ch: char) -> Option<&'static str> {
+    let script = match ch as u32 {
+        0x30..=0x39 | 0x5F => return None,
+        0x41..=0x24F | 0x1E00..=0x1EFF => "Latin",
+        0x370..=0x3FF | 0x1F00..=0x1FFF => "Greek",
+        0x400..=0x52F => "Cyrillic",
+        0x530..=0x58F => "Armenian",
+        0x590..=0x5FF => "Hebrew",
+        0x600..=0x6FF => "Arabic",
+        0x3040..=0x309F => "Hiragana",
+        0x30A0..=0x30FF => "Katakana",
+        0x4E00..=0x9FFF => "Han",
+        0xAC00..=0xD7AF => "Hangul",
+        _ => "Other"
+    };
+    Some(script)
+}
+
 #[cfg(test)]
 mod tokenize_should {
 
     use super::*;
+    use crate::IdentifierChars;
     use matches::assert_matches;
 
     fn next(val: &mut Tokens) -> Token {
@@ -196,9 +551,236 @@ mod tokenize_should {
         assert_matches!(tokens.next(), None)
     }
 
+    #[test]
+    fn separate_lines_with_newline_tokens() {
+        let mut tokens = Tokens::new("1 + 2\n\nmax(3,\n 4)\n").with_newline_separators();
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_eq!(next(&mut tokens), Token::Newline(5));
+        assert_matches!(next(&mut tokens), Token::Str(..));
+        assert_matches!(next(&mut tokens), Token::LParen(..));
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_matches!(next(&mut tokens), Token::Comma(..));
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_matches!(next(&mut tokens), Token::RParen(..));
+        assert_eq!(next(&mut tokens), Token::Newline(17));
+        assert_matches!(tokens.next(), None);
+        assert_eq!(Tokens::new("1\n2").count(), 2);
+    }
+
+    #[test]
+    fn error_on_malformed_numbers() {
+        let mut tokens = Tokens::new("2 * 1.2.3");
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+        let error = tokens.next().unwrap().unwrap_err();
+        assert_eq!(error.error, "Malformed number '1.2.3'");
+        assert_eq!(error.at, 4);
+        assert_matches!(Tokens::new("1..").next(), Some(Err(..)));
+        assert_matches!(Tokens::new("0x1.8q").next(), Some(Err(..)));
+    }
+
+    #[test]
+    fn handle_digit_separators() {
+        let mut tokens = Tokens::new("1_000 + 2_500.000_1");
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 0, len: 5 }));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 8, len: 11 }));
+        for text in &["1_", "1__0", "1_.5", "1._5", "1_000_"] {
+            let error = Tokens::new(text).next().unwrap().unwrap_err();
+            assert_eq!(error.error, format!("Malformed number '{}'", text));
+        }
+    }
+
+    #[test]
+    fn handle_exponents() {
+        let mut tokens = Tokens::new("1e3 + 2.5E-4 * 3e+2");
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 0, len: 3 }));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 6, len: 6 }));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 15, len: 4 }));
+    }
+
+    #[test]
+    fn handle_e_without_exponent_digits_as_identifier() {
+        let mut tokens = Tokens::new("1e");
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 0, len: 1 }));
+        assert_eq!(next(&mut tokens), Token::Str(Position { at: 1, len: 1 }));
+        let mut tokens = Tokens::new("1ex");
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 0, len: 1 }));
+        assert_eq!(next(&mut tokens), Token::Str(Position { at: 1, len: 2 }));
+        let mut tokens = Tokens::new("1e+x");
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 0, len: 1 }));
+        assert_eq!(next(&mut tokens), Token::Str(Position { at: 1, len: 1 }));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+    }
+
+    #[test]
+    fn handle_prefixed_numbers() {
+        let mut tokens = Tokens::new("0xff+0b11");
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 0, len: 4 }));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 5, len: 4 }));
+        let error = Tokens::new("0xGG").next().unwrap().unwrap_err();
+        assert_eq!(error.error, "Malformed number '0xGG'");
+    }
+
+    #[test]
+    fn handle_hex_float_numbers() {
+        let mut tokens = Tokens::new("0x1.8p+3-1");
+        assert_eq!(next(&mut tokens), Token::Number(Position { at: 0, len: 8 }));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+        assert_matches!(next(&mut tokens), Token::Number(..));
+    }
+
+    #[test]
+    fn tell_percent_from_remainder() {
+        let percent = |source| Tokens::new(source).next().unwrap().unwrap();
+        assert_eq!(percent("50%"), Token::Number(Position { at: 0, len: 3 }));
+        assert_eq!(percent("10% + 5"), Token::Number(Position { at: 0, len: 3 }));
+        assert_eq!(percent("10% xor 1"), Token::Number(Position { at: 0, len: 3 }));
+        for remainder in &["10 % 3", "10%3", "10% 3", "10%(3)", "10%x"] {
+            let mut tokens = Tokens::new(remainder);
+            assert_eq!(next(&mut tokens), Token::Number(Position { at: 0, len: 2 }));
+            assert_matches!(next(&mut tokens), Token::Operator{..});
+        }
+    }
+
+    #[test]
+    fn require_operator_spacing() {
+        let options = ParseOptions { require_operator_spacing: true, ..ParseOptions::default() };
+        let mut tokens = Tokens::with_options("1+2", options.clone());
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_eq!(tokens.next().unwrap().unwrap_err().at, 1);
+        let mut tokens = Tokens::with_options("1 + -2 * (3 - 1)", options);
+        assert!(tokens.all(|token| token.is_ok()));
+    }
+
+    #[test]
+    fn handle_ternary_punctuation() {
+        let mut tokens = Tokens::new("a?1:2");
+        assert_matches!(next(&mut tokens), Token::Str(..));
+        assert_eq!(next(&mut tokens), Token::Question(1));
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_eq!(next(&mut tokens), Token::Colon(3));
+        assert_matches!(next(&mut tokens), Token::Number(..));
+    }
+
+    #[test]
+    fn normalize_token_positions() {
+        let source = "max(a, 1) × 2";
+        let spans: Vec<_> = Tokens::new(source)
+            .map(|token| token.unwrap().position().map(|pos| &source[pos.to_range()]))
+            .collect();
+        assert_eq!(spans, vec![Some("max"), Some("("), Some("a"), Some(","), Some("1"), Some(")"), None, Some("2")]);
+        assert_eq!(tokenize_with_spans(source).unwrap()[6].1, Position { at: 10, len: 2 });
+    }
+
+    #[test]
+    fn handle_brackets() {
+        let mut tokens = Tokens::new("v[1]");
+        assert_matches!(next(&mut tokens), Token::Str(..));
+        assert_eq!(next(&mut tokens), Token::LBracket(1));
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_eq!(next(&mut tokens), Token::RBracket(3));
+        assert_matches!(tokens.next(), None);
+    }
+
+    #[test]
+    fn handle_equality_operators() {
+        let mut tokens = Tokens::new("1==1!=0");
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_eq!(next(&mut tokens), Token::Operator { at: 1, operator_ix: operator::find("=").unwrap() });
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_eq!(next(&mut tokens), Token::Operator { at: 4, operator_ix: operator::find("!=").unwrap() });
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        let options = ParseOptions { aliases: operator::OperatorAliases::new(), ..ParseOptions::default() };
+        let mut tokens = Tokens::with_options("1==1", options);
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_eq!(next(&mut tokens), Token::Operator { at: 1, operator_ix: operator::find("==").unwrap() });
+        assert_eq!(next(&mut Tokens::new("!1")), Token::Operator { at: 0, operator_ix: operator::find("!").unwrap() });
+    }
+
+    #[test]
+    fn translate_operator_aliases() {
+        let mut tokens = Tokens::new("1=>1 × 2");
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_eq!(next(&mut tokens), Token::Operator { at: 1, operator_ix: operator::find(">=").unwrap() });
+        assert_matches!(next(&mut tokens), Token::Number(Position { at: 3, .. }));
+        assert_eq!(next(&mut tokens), Token::Operator { at: 5, operator_ix: operator::find("*").unwrap() });
+        assert_matches!(next(&mut tokens), Token::Number(..));
+        assert_matches!(tokens.next(), None);
+    }
+
+    #[test]
+    fn span_whole_tokens() {
+        let spans = tokenize_with_spans("a >= 10 ÷ (b)").unwrap();
+        let positions: Vec<(u32, u16)> = spans.iter().map(|(_, pos)| (pos.at, pos.len)).collect();
+        assert_eq!(positions, vec![(0, 1), (2, 2), (5, 2), (8, 2), (11, 1), (12, 1), (13, 1)]);
+        assert_matches!(spans[1].0, Token::Operator { at: 2, .. });
+        assert_matches!(tokenize_with_spans("1 $ 2"), Err(..));
+    }
+
+    #[test]
+    fn reject_mixed_script_identifiers() {
+        let options = ParseOptions { reject_mixed_script_identifiers: true, ..ParseOptions::default() };
+        // the first two letters are Cyrillic
+        let mut tokens = Tokens::with_options("1 + раssword", options.clone());
+        let error = tokens.nth(2).unwrap().unwrap_err();
+        assert_eq!(error.error, "Identifier 'раssword' mixes Cyrillic and Latin scripts");
+        assert_eq!(error.at, 8);
+        assert!(Tokens::with_options("пароль + password_2 + αβ", options).all(|token| token.is_ok()));
+        assert!(Tokens::new("раssword").all(|token| token.is_ok()));
+    }
+
+    #[test]
+    fn handle_custom_identifier_chars() {
+        let identifier_chars = IdentifierChars {
+            is_start: |ch| ch.is_alphabetic() || ch == '-',
+            is_continue: |ch| ch.is_alphanumeric() || ch == '-',
+        };
+        let options = ParseOptions { identifier_chars, ..ParseOptions::default() };
+        let tokens: Vec<Token> = Tokens::with_options("--my-var + x-1", options.clone()).map(|token| token.unwrap()).collect();
+        assert_eq!(tokens, vec![
+            Token::Str(Position { at: 0, len: 8 }),
+            Token::Operator { at: 9, operator_ix: operator::find("+").unwrap() },
+            Token::Str(Position { at: 11, len: 3 }),
+        ]);
+        assert_matches!(Tokens::new("my-var").nth(1), Some(Ok(Token::Operator { .. })));
+        let minus = Token::Operator { at: 2, operator_ix: operator::find("-").unwrap() };
+        assert_eq!(Tokens::with_options("a - b", options.clone()).nth(1).unwrap().unwrap(), minus);
+        assert_eq!(Tokens::with_options("1 -2", options.clone()).nth(1).unwrap().unwrap(), minus);
+        assert_eq!(Tokens::with_options("(x) -y", options.clone()).nth(3).unwrap().unwrap(), Token::Operator { at: 4, operator_ix: operator::find("-").unwrap() });
+        assert_matches!(Tokens::new("a_1").nth(1), None);
+    }
+
+    #[test]
+    fn handle_keyword_operators() {
+        let mut tokens = Tokens::new("a xor xored");
+        assert_matches!(next(&mut tokens), Token::Str(..));
+        assert_matches!(next(&mut tokens), Token::Operator{..});
+        assert_matches!(next(&mut tokens), Token::Str(..));
+        assert_matches!(tokens.next(), None);
+    }
+
+    #[test]
+    fn list_allowed_chars_on_reserved_char() {
+        let error = Tokens::new("@").next().unwrap().unwrap_err();
+        assert_eq!(error.error, "Found reserved character @ at 0, expected a number, a name, one of ( ) [ ] , ? : or an operator: / * + - < > <= >= = && xor || % ^ == != ! => =< × ÷");
+        assert_matches!(Tokens::new("1 + @").nth(2), Some(Err(Error { at: 4, .. })));
+        // only the operators of the configured table are listed
+        let mut aliases = operator::OperatorAliases::replacing();
+        aliases.add("plus", "+");
+        let options = ParseOptions { aliases, ..ParseOptions::default() };
+        let error = Tokens::with_options("1 @", options).nth(1).unwrap().unwrap_err();
+        assert!(error.error.ends_with("or an operator: plus"));
+    }
+
     #[test]
     fn handle_single_dot_error() {
         let error = Tokens::new(" . ").next().unwrap().unwrap_err();
-        assert_eq!(error.at, 2);
+        assert_eq!(error.at, 1);
     }
 }
\ No newline at end of file
@@ -0,0 +1,69 @@
+use super::{Error, Expr, tokenizer, parser, valid_operator};
+
+/// What kind of value an expression produces. Booleans are still evaluated
+/// to 1 or 0, the distinction only tells how the result is meant to be read.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub enum ValueType {
+    Number,
+    Boolean,
+}
+
+/// Infers the type of the expression's result from its outermost operation,
+/// without evaluating it, so unknown variables are fine.
+pub fn result_type(expression: &str) -> Result<ValueType, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    root_type(&expr, expression)
+}
+
+pub(crate) fn root_type(expr: &Expr, source: &str) -> Result<ValueType, Error> {
+    match expr {
+        Expr::Binary(bin) if valid_operator(bin.operator_ix, bin.at)?.is_boolean() => Ok(ValueType::Boolean),
+        Expr::Unary{ operator_ix, at, .. } if valid_operator(*operator_ix, *at)?.is_boolean() => Ok(ValueType::Boolean),
+        Expr::Unary{ .. } => Ok(ValueType::Number),
+        Expr::Func(func) => match (&source[func.name.to_range()], &func.params[..]) {
+            ("approx" | "between", _) => Ok(ValueType::Boolean),
+            ("if", [_, then, otherwise]) => Ok(join(root_type(then, source)?, root_type(otherwise, source)?)),
+            _ => Ok(ValueType::Number)
+        },
+        Expr::Ternary(ternary) => Ok(join(root_type(&ternary.then, source)?, root_type(&ternary.otherwise, source)?)),
+        _ => Ok(ValueType::Number)
+    }
+}
+
+// either branch of a condition may be the result, so it is only boolean if both are
+fn join(then: ValueType, otherwise: ValueType) -> ValueType {
+    if then == otherwise { then } else { ValueType::Number }
+}
+
+
+#[cfg(test)]
+mod types_should {
+    use super::*;
+
+    #[test]
+    fn tell_booleans_from_numbers() {
+        assert_eq!(result_type("1 > 0").unwrap(), ValueType::Boolean);
+        assert_eq!(result_type("1 + 1").unwrap(), ValueType::Number);
+        assert_eq!(result_type("a < b && c xor d").unwrap(), ValueType::Boolean);
+        assert_eq!(result_type("between(x, 0, 1)").unwrap(), ValueType::Boolean);
+        assert_eq!(result_type("-(1 > 0)").unwrap(), ValueType::Number);
+        assert_eq!(result_type("!x").unwrap(), ValueType::Boolean);
+        assert_eq!(result_type("(unknown)").unwrap(), ValueType::Number);
+    }
+
+    #[test]
+    fn join_branch_types() {
+        assert_eq!(result_type("if(c, 1 > 0, 5)").unwrap(), ValueType::Number);
+        assert_eq!(result_type("if(c, 1 > 0, 2 > 1)").unwrap(), ValueType::Boolean);
+        assert_eq!(result_type("if(c, 1, 2)").unwrap(), ValueType::Number);
+        assert_eq!(result_type("c ? a < 1 : !b").unwrap(), ValueType::Boolean);
+        assert_eq!(result_type("c ? a < 1 : abs(b)").unwrap(), ValueType::Number);
+    }
+
+    #[test]
+    fn error_on_invalid_expression() {
+        assert_matches!(result_type("1 >"), Err(..));
+    }
+}
@@ -0,0 +1,221 @@
+use super::{Error, tokenizer, parser, numeric};
+use numeric::Numeric;
+use std::cmp::Ordering;
+
+const BASE_SYMBOLS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+// name, scale to SI base units, exponents of m, kg, s, A, K, mol, cd
+const UNITS: [(&str, f32, [i8; 7]); 18] = [
+    ("m", 1.0, [1, 0, 0, 0, 0, 0, 0]),
+    ("km", 1000.0, [1, 0, 0, 0, 0, 0, 0]),
+    ("cm", 0.01, [1, 0, 0, 0, 0, 0, 0]),
+    ("mm", 0.001, [1, 0, 0, 0, 0, 0, 0]),
+    ("kg", 1.0, [0, 1, 0, 0, 0, 0, 0]),
+    ("g", 0.001, [0, 1, 0, 0, 0, 0, 0]),
+    ("s", 1.0, [0, 0, 1, 0, 0, 0, 0]),
+    ("ms", 0.001, [0, 0, 1, 0, 0, 0, 0]),
+    ("min", 60.0, [0, 0, 1, 0, 0, 0, 0]),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0, 0]),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0]),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0]),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0]),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1]),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0]),
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0]),
+    ("J", 1.0, [2, 1, -2, 0, 0, 0, 0]),
+    ("W", 1.0, [2, 1, -3, 0, 0, 0, 0]),
+];
+
+/// A value together with the exponents of the seven SI base units
+/// (m, kg, s, A, K, mol, cd) it is measured in. Values are kept in base units,
+/// so `1 km` is stored as 1000 metres.
+#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy)]
+pub struct Quantity {
+    pub value: f32,
+    pub dimensions: [i8; 7]
+}
+
+impl Quantity {
+    pub fn dimensionless(value: f32) -> Quantity {
+        Quantity { value, dimensions: [0; 7] }
+    }
+
+    fn unit(name: &str) -> Option<Quantity> {
+        UNITS.iter()
+            .find(|(unit, ..)| *unit == name)
+            .map(|&(_, value, dimensions)| Quantity { value, dimensions })
+    }
+
+    fn same_dimensions(self, rhs: Quantity, action: &str) -> Result<(), String> {
+        if self.dimensions == rhs.dimensions {
+            Ok(())
+        } else {
+            Err(format!("Cannot {} {} and {}", action, describe(self.dimensions), describe(rhs.dimensions)))
+        }
+    }
+}
+
+fn describe(dimensions: [i8; 7]) -> String {
+    if dimensions == [0; 7] {
+        "a plain number".to_string()
+    } else {
+        symbol(dimensions)
+    }
+}
+
+// e.g. "m/s" or "m*kg/s^2"; empty for dimensionless values
+fn symbol(dimensions: [i8; 7]) -> String {
+    let part = |exponent: i8, symbol: &str| if exponent == 1 {
+        symbol.to_string()
+    } else {
+        format!("{}^{}", symbol, exponent)
+    };
+    let numerator: Vec<String> = BASE_SYMBOLS.iter().zip(dimensions.iter())
+        .filter(|(_, &exp)| exp > 0)
+        .map(|(symbol, &exp)| part(exp, symbol))
+        .collect();
+    let denominator: Vec<String> = BASE_SYMBOLS.iter().zip(dimensions.iter())
+        .filter(|(_, &exp)| exp < 0)
+        .map(|(symbol, &exp)| part(-exp, symbol))
+        .collect();
+    match (numerator.is_empty(), denominator.is_empty()) {
+        (true, true) => String::new(),
+        (_, true) => numerator.join("*"),
+        (true, _) => format!("1/{}", denominator.join("*")),
+        _ => format!("{}/{}", numerator.join("*"), denominator.join("*"))
+    }
+}
+
+pub(crate) fn is_unit(name: &str) -> bool {
+    Quantity::unit(name).is_some()
+}
+
+impl std::fmt::Display for Quantity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let symbol = symbol(self.dimensions);
+        if symbol.is_empty() {
+            write!(f, "{}", self.value)
+        } else {
+            write!(f, "{} {}", self.value, symbol)
+        }
+    }
+}
+
+impl Numeric for Quantity {
+    // a number optionally followed by a unit, as joined by the tokenizer
+    fn literal(text: &str) -> Result<Quantity, String> {
+        let split = text.char_indices()
+            .find(|&(ix, ch)| ch.is_whitespace() || (ch.is_alphabetic() && tokenizer::exponent_len(&text[ix..]) == 0))
+            .map_or(text.len(), |(ix, _)| ix);
+        let (number, unit) = text.split_at(split);
+        let number = number.parse::<f32>().map_err(|_| format!("Invalid number '{}'", number))?;
+        let unit = unit.trim_start();
+        if unit.is_empty() {
+            return Ok(Quantity::dimensionless(number));
+        }
+        let unit = Quantity::unit(unit).ok_or_else(|| format!("Unknown unit '{}'", unit))?;
+        Ok(Quantity { value: number * unit.value, dimensions: unit.dimensions })
+    }
+
+    // a unit on its own means one of it, as in `10 m / s`
+    fn variable(name: &str) -> Option<Quantity> {
+        Quantity::unit(name)
+    }
+
+    fn from_bool(value: bool) -> Quantity {
+        Quantity::dimensionless(if value {1.0} else {0.0})
+    }
+
+    fn truthy(&self) -> Result<bool, String> {
+        Ok(self.value > 0.0)
+    }
+
+    fn neg(self) -> Quantity {
+        Quantity { value: -self.value, dimensions: self.dimensions }
+    }
+
+    fn add(self, rhs: Quantity) -> Result<Quantity, String> {
+        self.same_dimensions(rhs, "apply '+' to")?;
+        Ok(Quantity { value: self.value + rhs.value, dimensions: self.dimensions })
+    }
+
+    fn sub(self, rhs: Quantity) -> Result<Quantity, String> {
+        self.same_dimensions(rhs, "apply '-' to")?;
+        Ok(Quantity { value: self.value - rhs.value, dimensions: self.dimensions })
+    }
+
+    fn mul(self, rhs: Quantity) -> Result<Quantity, String> {
+        let mut dimensions = self.dimensions;
+        for (dim, rhs_dim) in dimensions.iter_mut().zip(rhs.dimensions.iter()) {
+            *dim += rhs_dim;
+        }
+        Ok(Quantity { value: self.value * rhs.value, dimensions })
+    }
+
+    fn div(self, rhs: Quantity) -> Result<Quantity, String> {
+        let mut dimensions = self.dimensions;
+        for (dim, rhs_dim) in dimensions.iter_mut().zip(rhs.dimensions.iter()) {
+            *dim -= rhs_dim;
+        }
+        Ok(Quantity { value: self.value / rhs.value, dimensions })
+    }
+
+    fn compare(&self, rhs: &Quantity) -> Result<Option<Ordering>, String> {
+        self.same_dimensions(*rhs, "compare")?;
+        Ok(self.value.partial_cmp(&rhs.value))
+    }
+
+    fn call(name: &str, args: &[Quantity]) -> Option<Result<Quantity, String>> {
+        match (name, args) {
+            ("pi", []) => Some(Ok(Quantity::dimensionless(std::f32::consts::PI))),
+            ("e", []) => Some(Ok(Quantity::dimensionless(std::f32::consts::E))),
+            ("tau", []) => Some(Ok(Quantity::dimensionless(std::f32::consts::TAU))),
+            ("dist", &[a, b]) => Some(a.sub(b).map(|diff| Quantity { value: diff.value.abs(), dimensions: diff.dimensions })),
+            _ => None
+        }
+    }
+}
+
+/// Evaluates an expression whose numbers may carry units, e.g. `3 m / 2 s`,
+/// checking that additions and comparisons only combine matching dimensions.
+pub fn evaluate_units(expression: &str) -> Result<Quantity, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression).with_unit_literals();
+    let expr = parser::parse(&mut tokens)?;
+    numeric::eval(&expr, expression)
+}
+
+
+#[cfg(test)]
+mod units_should {
+    use super::*;
+
+    #[test]
+    fn derive_velocity() {
+        let velocity = evaluate_units("3 m / 2 s").unwrap();
+        assert_eq!(velocity, Quantity { value: 1.5, dimensions: [1, 0, -1, 0, 0, 0, 0] });
+        assert_eq!(velocity.to_string(), "1.5 m/s");
+    }
+
+    #[test]
+    fn error_on_dimension_mismatch() {
+        let error = evaluate_units("1 m + 1 s").unwrap_err();
+        assert_eq!(error.error, "Cannot apply '+' to m and s");
+        assert_eq!(error.at, 4);
+        assert_eq!(evaluate_units("1 m < 1 kg").unwrap_err().error, "Cannot compare m and kg");
+    }
+
+    #[test]
+    fn convert_to_base_units() {
+        assert_eq!(evaluate_units("1 km + 20 m").unwrap().value, 1020.0);
+        assert_eq!(evaluate_units("1e3 m + 1 km").unwrap().value, 2000.0);
+        assert_eq!(evaluate_units("1 min > 59 s").unwrap(), Quantity::dimensionless(1.0));
+        assert_eq!(evaluate_units("10 m / s").unwrap().to_string(), "10 m/s");
+        assert_eq!(evaluate_units("2 kg * 3 m / 1 s / 1 s").unwrap().to_string(), "6 m*kg/s^2");
+    }
+
+    #[test]
+    fn keep_plain_numbers_dimensionless() {
+        assert_eq!(evaluate_units("2 * (3 + 1)").unwrap(), Quantity::dimensionless(8.0));
+    }
+}
@@ -0,0 +1,172 @@
+use super::{Error, tokenizer, parser, numeric};
+use numeric::Numeric;
+use std::cmp::Ordering;
+
+/// A number or a vector of numbers, as computed by `evaluate_vector`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Scalar(f32),
+    Vector(Vec<f32>),
+}
+
+impl Value {
+    // applies `op` to each pair of elements, or to each element and the scalar
+    fn zip(self, rhs: Value, symbol: char, op: fn(f32, f32) -> f32) -> Result<Value, String> {
+        match (self, rhs) {
+            (Value::Scalar(a), Value::Scalar(b)) => Ok(Value::Scalar(op(a, b))),
+            (Value::Vector(a), Value::Vector(b)) if a.len() == b.len() =>
+                Ok(Value::Vector(a.iter().zip(&b).map(|(&a, &b)| op(a, b)).collect())),
+            (Value::Vector(a), Value::Vector(b)) =>
+                Err(format!("Cannot apply '{}' to vectors of length {} and {}", symbol, a.len(), b.len())),
+            // scaling, as in `2 * [1, 2]` or `[1, 2] / 2`
+            (Value::Scalar(a), Value::Vector(b)) if symbol == '*' => Ok(Value::Vector(b.iter().map(|&b| op(a, b)).collect())),
+            (Value::Vector(a), Value::Scalar(b)) if matches!(symbol, '*' | '/') => Ok(Value::Vector(a.iter().map(|&a| op(a, b)).collect())),
+            _ => Err(format!("Cannot apply '{}' to a vector and a number", symbol))
+        }
+    }
+
+    fn scalar(&self) -> Result<f32, String> {
+        match self {
+            Value::Scalar(value) => Ok(*value),
+            Value::Vector(_) => Err("Expected a number but got a vector".to_string())
+        }
+    }
+
+    fn vector(&self) -> Result<&[f32], String> {
+        match self {
+            Value::Vector(values) => Ok(values),
+            Value::Scalar(_) => Err("Expected a vector but got a number".to_string())
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Scalar(value) => write!(f, "{}", value),
+            Value::Vector(values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+impl Numeric for Value {
+    fn literal(text: &str) -> Result<Value, String> {
+        text.parse::<f32>().map(Value::Scalar).map_err(|_| format!("Invalid number '{}'", text))
+    }
+
+    fn variable(_name: &str) -> Option<Value> {
+        None
+    }
+
+    fn from_bool(value: bool) -> Value {
+        Value::Scalar(if value {1.0} else {0.0})
+    }
+
+    fn truthy(&self) -> Result<bool, String> {
+        Ok(self.scalar()? > 0.0)
+    }
+
+    fn neg(self) -> Value {
+        match self {
+            Value::Scalar(value) => Value::Scalar(-value),
+            Value::Vector(values) => Value::Vector(values.iter().map(|value| -value).collect())
+        }
+    }
+
+    fn add(self, rhs: Value) -> Result<Value, String> {
+        self.zip(rhs, '+', |a, b| a + b)
+    }
+
+    fn sub(self, rhs: Value) -> Result<Value, String> {
+        self.zip(rhs, '-', |a, b| a - b)
+    }
+
+    fn mul(self, rhs: Value) -> Result<Value, String> {
+        self.zip(rhs, '*', |a, b| a * b)
+    }
+
+    fn div(self, rhs: Value) -> Result<Value, String> {
+        self.zip(rhs, '/', |a, b| a / b)
+    }
+
+    // vectors have no order, only equality
+    fn compare(&self, rhs: &Value) -> Result<Option<Ordering>, String> {
+        self.scalar()?.partial_cmp(&rhs.scalar()?).map_or(Ok(None), |ordering| Ok(Some(ordering)))
+    }
+
+    fn equals(&self, rhs: &Value) -> Result<bool, String> {
+        match (self, rhs) {
+            (Value::Scalar(a), Value::Scalar(b)) => Ok(a == b),
+            (Value::Vector(a), Value::Vector(b)) => Ok(a == b),
+            _ => Err("Cannot compare a vector and a number".to_string())
+        }
+    }
+
+    fn call(name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+        let result = match (name, args) {
+            ("[", elements) => elements.iter().map(Value::scalar).collect::<Result<_, _>>().map(Value::Vector),
+            ("dot", [a, b]) => match (a.vector(), b.vector()) {
+                (Ok(a), Ok(b)) if a.len() == b.len() => Ok(Value::Scalar(a.iter().zip(b).map(|(a, b)| a * b).sum())),
+                (Ok(a), Ok(b)) => Err(format!("Cannot take 'dot' of vectors of length {} and {}", a.len(), b.len())),
+                (Err(error), _) | (_, Err(error)) => Err(error)
+            },
+            ("norm", [v]) => v.vector().map(|v| Value::Scalar(v.iter().map(|x| x * x).sum::<f32>().sqrt())),
+            ("len", [v]) => v.vector().map(|v| Value::Scalar(v.len() as f32)),
+            _ => return None
+        };
+        Some(result)
+    }
+}
+
+/// Evaluates the expression over numbers and vectors written `[1, 2, 3]`.
+/// `+`, `-`, `*` and `/` work element by element on vectors of the same length
+/// and `*` and `/` also scale a vector by a number. `dot`, `norm` and `len`
+/// take vectors.
+pub fn evaluate_vector(expression: &str) -> Result<Value, Error> {
+    let mut tokens = tokenizer::Tokens::new(expression);
+    let expr = parser::parse(&mut tokens)?;
+    numeric::eval(&expr, expression)
+}
+
+
+#[cfg(test)]
+mod vector_should {
+    use super::*;
+
+    #[test]
+    fn add_element_wise() {
+        assert_eq!(evaluate_vector("[1, 2] + [3, 4]").unwrap(), Value::Vector(vec![4.0, 6.0]));
+        assert_eq!(evaluate_vector("[1,2] + [3,4] == [4,6]").unwrap(), Value::Scalar(1.0));
+        assert_eq!(evaluate_vector("2 * [1, -2] - [0, 1] / 2").unwrap().to_string(), "[2, -4.5]");
+        assert_eq!(evaluate_vector("[]").unwrap(), Value::Vector(vec![]));
+    }
+
+    #[test]
+    fn error_on_length_mismatch_and_mixing() {
+        let error = evaluate_vector("[1, 2] + [1, 2, 3]").unwrap_err();
+        assert_eq!(error.message(), "Cannot apply '+' to vectors of length 2 and 3");
+        assert_eq!(error.at(), 7);
+        assert_eq!(evaluate_vector("1 + [1]").unwrap_err().message(), "Cannot apply '+' to a vector and a number");
+        assert_eq!(evaluate_vector("[[1], 2]").unwrap_err().message(), "Expected a number but got a vector");
+        assert_eq!(evaluate_vector("[1, 2").unwrap_err().at(), 0);
+        assert_matches!(evaluate_vector("[1 2]"), Err(..));
+    }
+
+    #[test]
+    fn compute_dot_product_and_norm() {
+        assert_eq!(evaluate_vector("dot([1, 2], [3, 4])").unwrap(), Value::Scalar(11.0));
+        assert_eq!(evaluate_vector("norm([3, 4])").unwrap(), Value::Scalar(5.0));
+        assert_eq!(evaluate_vector("len([3, 4, 5]) > 2 ? 1 : 0").unwrap(), Value::Scalar(1.0));
+        assert_matches!(evaluate_vector("dot([1], [1, 2])"), Err(..));
+        assert_matches!(evaluate_vector("dot(1, [1])"), Err(..));
+    }
+
+    #[test]
+    fn leave_vectors_to_evaluate_vector() {
+        assert_eq!(crate::evaluate("[1, 2]").unwrap_err().message(), "Vectors are only supported by evaluate_vector");
+        assert_eq!(crate::parse("[1,2] + v[0]").unwrap().to_string(), "[1, 2] + v[0]");
+    }
+}